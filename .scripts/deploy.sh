@@ -4,13 +4,22 @@ set -v
 
 ./build.sh
 
+## The static site reads the data from a snapshot instead of the api,
+## it is built apart from `client/pkg` which is embedded into the server
+snapshot_pkg="target/snapshot-pkg"
+wasm-pack build client --release --target web --out-dir "../$snapshot_pkg" -- --features snapshot
+
 dest="../ivanceras.github.io/hackernews"
 
 rm -rf "$dest"
 
 mkdir -p "$dest"
 
-cp -r client/index.html client/style.css client/pkg "$dest/"
+cp -r client/index.html client/style.css "$dest/"
+
+cp -r "$snapshot_pkg" "$dest/pkg"
+
+./target/release/server snapshot --out "$dest/snapshot.json"
 
 ## Remove the ignore file on the pkg directory
 rm $dest/pkg/.gitignore
@@ -5,4 +5,4 @@ cd "$(dirname "$0")"
 
 wasm-pack build client --release --target web
 
-cargo build --release --bin server
+cargo build --release --bin server --features embed-assets
@@ -1,9 +1,15 @@
 use common::types::{
-    Comment, StoryItem, StoryPageData, StorySorting, UserData,
+    Comment, Fetched, StoryList, StoryPageData, StorySorting, UserData,
+};
+#[cfg(feature = "wasm")]
+use common::{
+    route::Route,
+    types::{CommentFetchOptions, StoryItem, StoryOrder},
 };
 pub use content::Content;
 use sauron::prelude::*;
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
 #[cfg(feature = "wasm")]
 use common::api;
 use common::api::ServerError;
@@ -20,15 +26,25 @@ pub enum FetchStatus<T> {
 pub enum Msg {
     FetchStories,
     FetchStoriesSorted(StorySorting),
+    /// fetch this page of the stories listing
+    FetchStoriesPage(StorySorting, usize),
     OpenStory(i64),
     /// show the user data of this username
     ShowUserPage(String),
     ShowCommentPermalink(i64),
-    /// the new url and the Content
-    ReceivedContent(Content),
+    /// fetch these replies of the comment, or top-level comments of the story, with this id
+    LoadMoreReplies(i64, Vec<i64>),
+    /// the fetched replies of the comment, or top-level comments of the story, with this id
+    ReceivedReplies(i64, Fetched<Comment>),
+    /// collapse or expand the comment thread with this id
+    ToggleComment(i64),
+    /// the new url and the Content, boxed since it is much larger than the other messages
+    ReceivedContent(Box<Content>),
     RequestError(ServerError),
     /// the new url
     UrlChanged(String),
+    /// fetch the content of the current url again, ie: to retry the items which failed to load
+    Reload,
     NoOp,
 }
 
@@ -39,6 +55,9 @@ pub struct App {
     pub content: FetchStatus<Content>,
     /// is the page loading
     is_loading: bool,
+    /// the comment threads which are collapsed
+    #[serde(default)]
+    collapsed_comments: BTreeSet<i64>,
 }
 
 impl Default for App {
@@ -46,6 +65,7 @@ impl Default for App {
         Self {
             content: FetchStatus::Idle,
             is_loading: true,
+            collapsed_comments: BTreeSet::new(),
         }
     }
 }
@@ -59,11 +79,7 @@ impl Application for App {
         Cmd::batch([
             Window::on_popstate(|_e| {
                 log::trace!("pop_state is triggered in sauron add event listener");
-                let url = sauron::window()
-                    .location()
-                    .pathname()
-                    .expect("must have get a pathname");
-                Msg::UrlChanged(url)
+                Msg::UrlChanged(Self::current_url())
             }),
             match self.content{
                 FetchStatus::Idle => {
@@ -131,12 +147,17 @@ impl Application for App {
             Msg::FetchStoriesSorted(sorting) => {
                 Self::push_state_url(&sorting.to_url());
                 self.is_loading = true;
-                self.fetch_stories_with_sorting(sorting)
+                self.fetch_stories_with_sorting(sorting, 1)
+            }
+            Msg::FetchStoriesPage(sorting, page) => {
+                Self::push_state_url(&sorting.to_page_url(page));
+                self.is_loading = true;
+                self.fetch_stories_with_sorting(sorting, page)
             }
             Msg::OpenStory(story_id) => {
                 Self::push_state_url(&StoryItem::to_url(story_id));
                 self.is_loading = true;
-                self.fetch_story_page(story_id)
+                self.fetch_story_page(story_id, CommentFetchOptions::default())
             }
             Msg::ShowUserPage(username) => {
                 Self::push_state_url(&UserData::to_url(&username));
@@ -150,8 +171,25 @@ impl Application for App {
                 log::trace!("showing comment: {}", comment_id);
                 self.fetch_comment_permalink(comment_id)
             }
+            Msg::LoadMoreReplies(parent_id, comment_ids) => {
+                self.is_loading = true;
+                self.fetch_replies(parent_id, comment_ids)
+            }
+            Msg::ReceivedReplies(parent_id, replies) => {
+                if let FetchStatus::Complete(content) = &mut self.content {
+                    content.add_replies(parent_id, replies);
+                }
+                self.is_loading = false;
+                Cmd::none()
+            }
+            Msg::ToggleComment(comment_id) => {
+                if !self.collapsed_comments.remove(&comment_id) {
+                    self.collapsed_comments.insert(comment_id);
+                }
+                Cmd::none()
+            }
             Msg::ReceivedContent(content) => {
-                self.content = FetchStatus::Complete(content);
+                self.content = FetchStatus::Complete(*content);
                 self.is_loading = false;
                 Cmd::from(Window::scroll_to_top(Msg::NoOp))
             }
@@ -164,20 +202,30 @@ impl Application for App {
             Msg::UrlChanged(url) => {
                 self.is_loading = true;
                 log::trace!("url changed to: {}", url);
-                let cmd = if let Some(sorting) = StorySorting::from_url(&url) {
-                    self.is_loading = true;
-                    self.fetch_stories_with_sorting(sorting)
-                } else if let Some(story_id) = StoryItem::id_from_url(&url) {
-                    self.fetch_story_page(story_id)
-                } else if let Some(comment_id) = Comment::id_from_url(&url) {
-                    self.fetch_comment_permalink(comment_id)
-                } else if let Some(username) = UserData::id_from_url(&url) {
-                    self.fetch_user_page(username)
-                } else if "/" == url.trim() {
-                    self.fetch_stories()
-                } else {
-                    log::trace!("No appropriate route found for url: {}", url);
-                    Cmd::none()
+                let cmd = match Route::parse(&url) {
+                    Some(Route::Stories { sorting, page }) => {
+                        self.fetch_stories_with_sorting(sorting, page)
+                    }
+                    Some(Route::Item { id, options }) => {
+                        self.fetch_story_page(id, options)
+                    }
+                    Some(Route::Comment(comment_id)) => {
+                        self.fetch_comment_permalink(comment_id)
+                    }
+                    Some(Route::User(username)) => {
+                        self.fetch_user_page(username)
+                    }
+                    None => {
+                        log::trace!(
+                            "No appropriate route found for url: {}",
+                            url
+                        );
+                        self.is_loading = false;
+                        self.content = FetchStatus::Complete(
+                            Content::NotFound(url.clone()),
+                        );
+                        Cmd::none()
+                    }
                 };
 
                 Cmd::batch(vec![
@@ -185,6 +233,7 @@ impl Application for App {
                     Cmd::from(Window::scroll_to_top(Msg::NoOp)),
                 ])
             }
+            Msg::Reload => self.update(Msg::UrlChanged(Self::current_url())),
             Msg::NoOp => Cmd::none(),
         }
     }
@@ -202,7 +251,9 @@ impl App {
                     </article>
                 }
             }
-            FetchStatus::Complete(content) => content.view(),
+            FetchStatus::Complete(content) => {
+                content.view(&self.collapsed_comments)
+            }
         }
     }
 
@@ -243,22 +294,43 @@ impl App {
 }
 
 impl App {
-    pub fn with_stories(stories: Vec<StoryItem>) -> Self {
+    pub fn with_stories(stories: StoryList) -> Self {
         Self {
             content: FetchStatus::Complete(Content::from(stories)),
             is_loading: false,
+            collapsed_comments: BTreeSet::new(),
         }
     }
     pub fn with_story(story_page: StoryPageData) -> Self {
         Self {
             content: FetchStatus::Complete(Content::from(story_page)),
             is_loading: false,
+            collapsed_comments: BTreeSet::new(),
         }
     }
     pub fn with_user_page(user_data: UserData) -> Self {
         Self {
             content: FetchStatus::Complete(Content::from(user_data)),
             is_loading: false,
+            collapsed_comments: BTreeSet::new(),
+        }
+    }
+
+    /// show that there is nothing at this path
+    pub fn with_not_found(path: &str) -> Self {
+        Self {
+            content: FetchStatus::Complete(Content::NotFound(path.to_string())),
+            is_loading: false,
+            collapsed_comments: BTreeSet::new(),
+        }
+    }
+
+    /// show the error in place of the content
+    pub fn with_error(error: String) -> Self {
+        Self {
+            content: FetchStatus::Error(error),
+            is_loading: false,
+            collapsed_comments: BTreeSet::new(),
         }
     }
 
@@ -266,6 +338,7 @@ impl App {
         Self {
             content: FetchStatus::Complete(Content::from(comment)),
             is_loading: false,
+            collapsed_comments: BTreeSet::new(),
         }
     }
 }
@@ -277,9 +350,9 @@ impl App{
         Cmd::new( async move{
             match api::get_stories().await {
                 Ok(stories) => {
-                    Msg::ReceivedContent( Content::from(
+                    Msg::ReceivedContent(Box::new(Content::from(
                         stories,
-                    ))
+                    )))
                 }
                 Err(e) => {
                     Msg::RequestError(e)
@@ -291,13 +364,14 @@ impl App{
     fn fetch_stories_with_sorting(
         &self,
         sorting: StorySorting,
+        page: usize,
     ) -> Cmd<Msg> {
         Cmd::new( async move{
-            match api::get_stories_with_sorting(sorting).await {
+            match api::get_stories_with_sorting(sorting, page, StoryOrder::Rank).await {
                 Ok(stories) => {
-                    Msg::ReceivedContent( Content::from(
+                    Msg::ReceivedContent(Box::new(Content::from(
                         stories,
-                    ))
+                    )))
                 }
                 Err(e) => {
                     Msg::RequestError(e)
@@ -307,13 +381,17 @@ impl App{
     }
 
 
-    fn fetch_story_page(&self, story_id: i64) -> Cmd<Msg> {
+    fn fetch_story_page(
+        &self,
+        story_id: i64,
+        options: CommentFetchOptions,
+    ) -> Cmd<Msg> {
         Cmd::new( async move{
-            match api::get_story(story_id).await {
+            match api::get_story_with_options(story_id, &options).await {
                 Ok(story) => {
-                    Msg::ReceivedContent( Content::from(
+                    Msg::ReceivedContent(Box::new(Content::from(
                         story,
-                    ))
+                    )))
                 }
                 Err(e) => {
                     Msg::RequestError(e)
@@ -327,9 +405,9 @@ impl App{
         Cmd::new( async move{
             match api::get_comment(comment_id).await {
                 Ok(comment) => {
-                    Msg::ReceivedContent( Content::from(
+                    Msg::ReceivedContent(Box::new(Content::from(
                         comment,
-                    ))
+                    )))
                 }
                 Err(e) => {
                     Msg::RequestError(e)
@@ -339,13 +417,25 @@ impl App{
     }
 
 
+    fn fetch_replies(&self, parent_id: i64, comment_ids: Vec<i64>) -> Cmd<Msg> {
+        Cmd::new( async move{
+            let replies = api::get_comment_list_with_options(
+                &comment_ids,
+                &CommentFetchOptions::default(),
+            )
+            .await;
+            Msg::ReceivedReplies(parent_id, replies)
+        })
+    }
+
+
     fn fetch_user_page(&self, username: String) -> Cmd<Msg> {
         Cmd::new( async move{
             match api::get_user_page(&username).await {
                 Ok(user_page) => {
-                    Msg::ReceivedContent( Content::from(
+                    Msg::ReceivedContent(Box::new(Content::from(
                         user_page,
-                    ))
+                    )))
                 }
                 Err(e) => {
                     Msg::RequestError(e)
@@ -354,6 +444,16 @@ impl App{
         })
     }
 
+    /// the path and query of the url in the address bar
+    fn current_url() -> String {
+        let location = sauron::window().location();
+        format!(
+            "{}{}",
+            location.pathname().expect("must have get a pathname"),
+            location.search().expect("must have get a search")
+        )
+    }
+
     fn push_state_url(url: &str) {
         let history = sauron::window().history().expect("must have history");
         log::trace!("pushing to state: {}", url);
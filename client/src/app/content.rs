@@ -1,33 +1,66 @@
 use crate::app;
-use common::types::{Comment, StoryItem, StoryPageData, UserData};
+use common::types::{
+    Comment, CommentFetchOptions, Fetched, ItemType, PollOption, StoryItem,
+    StoryList, StoryPageData, StorySorting, UserData,
+};
 use sauron::prelude::*;
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
 //use sauron::safe_html;
 
 #[derive(
     Debug, Deserialize, Serialize, PartialEq, Clone, derive_more::From,
 )]
 pub enum Content {
-    Stories(Vec<StoryItem>),
+    Stories(StoryList),
     StoryPage(StoryPageData),
     CommentPermalink(Comment),
     UserPage(UserData),
+    /// there is nothing at this path
+    NotFound(String),
 }
 
 impl Content {
-    pub fn view(&self) -> Node<app::Msg> {
+    /// add the fetched replies to the comment with `parent_id`,
+    /// or as top-level comments if `parent_id` is the story being displayed
+    pub fn add_replies(&mut self, parent_id: i64, replies: Fetched<Comment>) {
         match self {
-            Content::Stories(stories) => {
+            Content::StoryPage(story_page) if story_page.id == parent_id => {
+                story_page.add_comments(replies);
+            }
+            Content::StoryPage(story_page) => {
+                if let Some(comment) = story_page.find_comment_mut(parent_id) {
+                    comment.add_replies(replies);
+                }
+            }
+            Content::CommentPermalink(comment) => {
+                if let Some(comment) = comment.find_mut(parent_id) {
+                    comment.add_replies(replies);
+                }
+            }
+            _ => (),
+        }
+    }
+
+    /// view the content, where the comment threads in `collapsed` are folded
+    pub fn view(&self, collapsed: &BTreeSet<i64>) -> Node<app::Msg> {
+        match self {
+            Content::Stories(story_list) => {
+                let more = story_list
+                    .next_page()
+                    .map(|next_page| (story_list.sorting, next_page));
                 node! {
                     <div class="index-page">
-                       {self.view_story_preview_list(stories)}
+                       { self.view_failed(story_list.failed.len(), story_list.sorting.to_page_url(story_list.page)) }
+                       {self.view_story_preview_list(&story_list.stories, story_list.offset(), more)}
                     </div>
                 }
             }
             Content::StoryPage(story_page) => {
                 node! {
                     <div class="story-page">
-                        { self.view_story_page(story_page) }
+                        { self.view_failed(story_page.failed_count(), StoryItem::to_url(story_page.id)) }
+                        { self.view_story_page(story_page, collapsed) }
                     </div>
                 }
             }
@@ -37,8 +70,9 @@ impl Content {
                         <h4>{ text!("{}:",user_data.id) }</h4>
                         <p>{ raw_html(&user_data.about) }</p>
                         <span>{ text!("{} karma", user_data.karma) }</span>
+                        { self.view_failed(user_data.failed.len(), UserData::to_url(&user_data.id)) }
                         <div class="submissions">
-                             {self.view_story_preview_list(&user_data.stories)}
+                             {self.view_story_preview_list(&user_data.stories, 0, None)}
                         </div>
                     </div>
                 }
@@ -46,29 +80,100 @@ impl Content {
             Content::CommentPermalink(comment) => {
                 node! {
                     <div class="comment-permalink">
-                        {self.view_comment(comment)}
+                        { self.view_failed(comment.failed_count(), Comment::to_url(comment.id)) }
+                        {self.view_comment(comment, collapsed)}
                     </div>
                 }
             }
+            Content::NotFound(path) => {
+                node! {
+                    <article class="not-found">
+                        <h2>"Page not found"</h2>
+                        <p>
+                        {
+                            // the exported 404 page is not rendered for any path in particular
+                            if path.is_empty() {
+                                text("There is nothing at this address")
+                            } else {
+                                text!("There is nothing at {}", path)
+                            }
+                        }
+                        </p>
+                        <a href="/"
+                            on_click=|e|{
+                                e.prevent_default();
+                                app::Msg::FetchStories
+                            }>
+                            "Go to the front page"
+                        </a>
+                    </article>
+                }
+            }
         }
     }
 
-    fn view_story_preview_list(&self, stories: &[StoryItem]) -> Node<app::Msg> {
+    /// a marker of the `failed` items which failed to load, with a link to fetch them again.
+    /// Without javascript, the link navigates to the `url` of the page instead.
+    fn view_failed(&self, failed: usize, url: String) -> Node<app::Msg> {
+        if failed == 0 {
+            return node! { <span></span> };
+        }
+        let label = if failed == 1 {
+            "1 item failed to load — retry".to_string()
+        } else {
+            format!("{} items failed to load — retry", failed)
+        };
         node! {
-            <ol>
-            {
-                for (i, story_preview) in stories.iter().enumerate() {
-                    node! {
-                        <li>
-                            <div class="item-number">{text!("{}. ",i+1)}</div>
-                            <div class="preview-wrapper">
-                                {self.view_story_preview(story_preview)}
-                            </div>
-                        </li>
+            <a class="failed-items" href=url
+                on_click=|e|{
+                    e.prevent_default();
+                    app::Msg::Reload
+                }>
+                { text(label) }
+            </a>
+        }
+    }
+
+    /// view the stories numbered starting after `offset`,
+    /// and a link to the `more` page of the listing if there is one.
+    fn view_story_preview_list(
+        &self,
+        stories: &[StoryItem],
+        offset: usize,
+        more: Option<(StorySorting, usize)>,
+    ) -> Node<app::Msg> {
+        node! {
+            <div>
+                <ol>
+                {
+                    for (i, story_preview) in stories.iter().enumerate() {
+                        node! {
+                            <li>
+                                <div class="item-number">{text!("{}. ",offset+i+1)}</div>
+                                <div class="preview-wrapper">
+                                    {self.view_story_preview(story_preview)}
+                                </div>
+                            </li>
+                        }
                     }
                 }
-            }
-            </ol>
+                </ol>
+                {
+                    if let Some((sorting, page)) = more {
+                        node! {
+                            <a class="more-link" href=sorting.to_page_url(page)
+                                on_click=move|e|{
+                                    e.prevent_default();
+                                    app::Msg::FetchStoriesPage(sorting, page)
+                                }>
+                                "More"
+                            </a>
+                        }
+                    } else {
+                        node! { <span></span> }
+                    }
+                }
+            </div>
         }
     }
 
@@ -81,6 +186,13 @@ impl Content {
         // of dynamic variables and move it.
         let story_preview_id = story_preview.id;
         let story_preview_by = story_preview.by.clone();
+        let title = if story_preview.deleted {
+            "[deleted]".to_string()
+        } else if story_preview.dead {
+            format!("[dead] {}", story_preview.title)
+        } else {
+            story_preview.title.to_owned()
+        };
         node! {
             <div class="story-preview">
                 <div class="buttons">
@@ -92,58 +204,159 @@ impl Content {
                     {
                         if let Some(url) = &story_preview.url{
                             node!{
-                                <a href=url target="_blank" rel="noopener noreferrer">{text(&story_preview.title)}</a>
+                                <a href=url target="_blank" rel="noopener noreferrer">{text(&title)}</a>
                             }
                         }else{
-                            text(&story_preview.title)
+                            text(&title)
                         }
                     }
                     </h2>
-                    <span class="story-details">
-                        {  text!("{} points | ",story_preview.score) }
-                        <a href=format!("/user/{}", story_preview.by)
-                            on_click=move|e|{
-                                e.prevent_default();
-                                app::Msg::ShowUserPage(story_preview_by.clone())
-                            }>
-                            { text!(" by {}",story_preview.by) }
-                        </a>
-                        <span title="time">{ text!(" | {} ago |", crate::util::time_ago(story_preview.time)) }</span>
-                        <a href=format!("/item/{}", story_preview.id)
-                            on_click=move|e|{
-                                e.prevent_default();
-                                app::Msg::OpenStory(story_preview_id)
-                            }>
-                            { text!(" {} comments", story_preview.descendants) }
-                        </a>
-                    </span>
+                    {
+                        // job posts have no score, author or comments
+                        if story_preview.r#type == ItemType::Job {
+                            node! {
+                                <span class="story-details">
+                                    <a href=format!("/item/{}", story_preview.id)
+                                        on_click=move|e|{
+                                            e.prevent_default();
+                                            app::Msg::OpenStory(story_preview_id)
+                                        }>
+                                        { text!("{} ago", crate::util::time_ago(story_preview.time)) }
+                                    </a>
+                                </span>
+                            }
+                        } else {
+                            node! {
+                                <span class="story-details">
+                                    {  text!("{} points | ",story_preview.score) }
+                                    <a href=UserData::to_url(&story_preview.by)
+                                        on_click=move|e|{
+                                            e.prevent_default();
+                                            app::Msg::ShowUserPage(story_preview_by.clone())
+                                        }>
+                                        { text!(" by {}",story_preview.by) }
+                                    </a>
+                                    <span title="time">{ text!(" | {} ago |", crate::util::time_ago(story_preview.time)) }</span>
+                                    <a href=format!("/item/{}", story_preview.id)
+                                        on_click=move|e|{
+                                            e.prevent_default();
+                                            app::Msg::OpenStory(story_preview_id)
+                                        }>
+                                        { text!(" {} comments", story_preview.descendants) }
+                                    </a>
+                                </span>
+                            }
+                        }
+                    }
                 </div>
             </div>
         }
     }
 
-    fn view_story_page(&self, story_page: &StoryPageData) -> Node<app::Msg> {
+    fn view_story_page(
+        &self,
+        story_page: &StoryPageData,
+        collapsed: &BTreeSet<i64>,
+    ) -> Node<app::Msg> {
+        let missing = story_page.missing_kids();
+        let label = if missing.len() == 1 {
+            "1 more comment".to_string()
+        } else {
+            format!("{} more comments", missing.len())
+        };
+        // without javascript, show the story with more of its top-level comments
+        let fallback_url = format!(
+            "{}?children={}",
+            StoryItem::to_url(story_page.id),
+            CommentFetchOptions::MAX.max_children_per_level
+        );
         node! {
             <div>
                 { self.view_story_preview(&story_page.preview()) }
+                {
+                    if let Some(story_text) = &story_page.text {
+                        node! { <div class="story-text">{ raw_html(story_text) }</div> }
+                    } else {
+                        node! { <span></span> }
+                    }
+                }
+                {
+                    if story_page.r#type == ItemType::Poll {
+                        self.view_poll_options(&story_page.poll_options)
+                    } else {
+                        node! { <span></span> }
+                    }
+                }
                 <ul class="comment-component">
                 {
                     for comment in story_page.comments.iter(){
-                        self.view_comment(comment)
+                        self.view_comment(comment, collapsed)
                     }
                 }
                 </ul>
+                { self.view_more_replies(story_page.id, missing, label, fallback_url) }
             </div>
         }
     }
 
-    fn view_comment(&self, comment: &Comment) -> Node<app::Msg> {
+    fn view_poll_options(&self, poll_options: &[PollOption]) -> Node<app::Msg> {
+        node! {
+            <ul class="poll-options">
+            {
+                for poll_option in poll_options {
+                    node! {
+                        <li class="poll-option">
+                            <div class="poll-option-text">{ raw_html(&poll_option.text) }</div>
+                            <span class="story-details">{ text!("{} points", poll_option.score) }</span>
+                        </li>
+                    }
+                }
+            }
+            </ul>
+        }
+    }
+
+    /// a link to fetch the replies of `parent_id` which are not yet fetched.
+    /// Without javascript, the link navigates to the `fallback_url` instead.
+    fn view_more_replies(
+        &self,
+        parent_id: i64,
+        missing: Vec<i64>,
+        label: String,
+        fallback_url: String,
+    ) -> Node<app::Msg> {
+        if missing.is_empty() {
+            return node! { <span></span> };
+        }
+        node! {
+            <a class="more-replies" href=fallback_url
+                on_click=move|e|{
+                    e.prevent_default();
+                    app::Msg::LoadMoreReplies(parent_id, missing.clone())
+                }>
+                { text(label) }
+            </a>
+        }
+    }
+
+    fn view_comment(
+        &self,
+        comment: &Comment,
+        collapsed: &BTreeSet<i64>,
+    ) -> Node<app::Msg> {
         let comment_id = comment.id;
         let comment_by = comment.by.clone();
+        let is_collapsed = collapsed.contains(&comment.id);
         node! {
             <li class="comment-item">
                 <div class="comment-details">
-                    <a href=format!("/user/{}",comment.by)
+                    <a class="comment-toggle"
+                        on_click=move|e|{
+                            e.prevent_default();
+                            app::Msg::ToggleComment(comment_id)
+                        }>{text(if is_collapsed { "[+]" } else { "[-]" })}
+                    </a>
+                    <a href=UserData::to_url(&comment.by)
                         on_click=move|e|{
                             e.prevent_default();
                             app::Msg::ShowUserPage(comment_by.clone())
@@ -155,20 +368,65 @@ impl Content {
                             app::Msg::ShowCommentPermalink(comment_id)
                         }>{text!(" {} ago", crate::util::time_ago(comment.time))}
                     </a>
+                    {
+                        if is_collapsed {
+                            node! {
+                                <span class="hidden-count">
+                                    {text!("({} hidden)", comment.descendant_count())}
+                                </span>
+                            }
+                        } else {
+                            node! { <span></span> }
+                        }
+                    }
                 </div>
-                <div class="comment">{ raw_html(&comment.text) }</div>
+                {
+                    if is_collapsed {
+                        node! { <span></span> }
+                    } else {
+                        self.view_comment_thread(comment, collapsed)
+                    }
+                }
+            </li>
+        }
+    }
+
+    /// the text and the replies of an expanded comment
+    fn view_comment_thread(
+        &self,
+        comment: &Comment,
+        collapsed: &BTreeSet<i64>,
+    ) -> Node<app::Msg> {
+        let missing = comment.missing_kids();
+        let label = if missing.len() == 1 {
+            "1 more reply".to_string()
+        } else {
+            format!("{} more replies", missing.len())
+        };
+        node! {
+            <div>
+                {
+                    if comment.deleted {
+                        node! { <div class="comment">"[deleted]"</div> }
+                    } else if comment.dead {
+                        node! { <div class="comment dead">"[dead]"</div> }
+                    } else {
+                        node! { <div class="comment">{ raw_html(&comment.text) }</div> }
+                    }
+                }
                 <ul class="sub-comments">
                 {
                     for sub in &comment.sub_comments{
                         node!{
                             <li>
-                                {self.view_comment(sub)}
+                                {self.view_comment(sub, collapsed)}
                             </li>
                         }
                     }
                 }
                 </ul>
-            </li>
+                { self.view_more_replies(comment.id, missing, label, Comment::to_url(comment.id)) }
+            </div>
         }
     }
 }
@@ -3,6 +3,7 @@
 pub use app::{App, Msg};
 use sauron::prelude::*;
 pub use sauron;
+use common::api;
 
 mod app;
 pub mod util;
@@ -19,6 +20,9 @@ pub async fn main(serialized_state: String) {
         console_error_panic_hook::set_once();
     }
 
+    #[cfg(feature = "snapshot")]
+    load_snapshot().await;
+
     let app = match serde_json::from_str::<App>(&serialized_state) {
         Ok(app_state) => app_state,
         Err(e) => {
@@ -28,3 +32,37 @@ pub async fn main(serialized_state: String) {
     };
     Program::replace_mount(app, &sauron::dom::util::body());
 }
+
+/// Point the api calls made by the client to a different hacker news api,
+/// such as a mirror or a local stand-in of it.
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+pub fn set_api_base_url(base_url: String) {
+    match api::HnClient::new(base_url) {
+        Ok(client) => api::set_default_client(client),
+        Err(e) => log::warn!("error: {}", e),
+    }
+}
+
+/// Read the data of the app from the snapshot next to the page instead of the api,
+/// so the app works on static hosting sites.
+#[cfg(feature = "snapshot")]
+async fn load_snapshot() {
+    use common::snapshot::{Snapshot, SNAPSHOT_FILE};
+
+    let href = sauron::window()
+        .location()
+        .href()
+        .expect("must have a href");
+    let page = href.split(|c| c == '?' || c == '#').next().unwrap_or_default();
+    let dir = &page[..page.rfind('/').map(|i| i + 1).unwrap_or(page.len())];
+    let url = format!("{}{}", dir, SNAPSHOT_FILE);
+    match Snapshot::fetch(&url).await {
+        Ok(snapshot) => {
+            match api::HnClient::builder().snapshot(snapshot).build() {
+                Ok(client) => api::set_default_client(client),
+                Err(e) => log::warn!("error: {}", e),
+            }
+        }
+        Err(e) => log::warn!("unable to load the snapshot at {}: {}", url, e),
+    }
+}
@@ -1,14 +1,66 @@
 use async_recursion::async_recursion;
+use crate::in_flight::{
+    InFlight,
+    Response,
+};
+use crate::retry::{
+    is_transient_status,
+    sleep,
+    with_timeout,
+};
+use crate::snapshot::Snapshot;
 use crate::types::{
-    Comment, StoryItem, StoryPageData, StorySorting, UserData,
+    Comment, CommentFetchOptions, FailedItem, Fetched, Item, ItemType,
+    PollOption, StoryItem, StoryList, StoryOrder, StoryPageData, StorySorting,
+    UserData,
 };
 use futures::future::join_all;
+use serde::de::DeserializeOwned;
+use std::{
+    future::Future,
+    sync::{
+        atomic::{
+            AtomicUsize,
+            Ordering,
+        },
+        Arc,
+        RwLock,
+    },
+    time::Duration,
+};
 use thiserror::Error;
 
 #[cfg(feature = "caching")]
-use std::sync::Mutex;
+pub use crate::cache::{
+    CacheEntry,
+    CacheStore,
+    CacheTtl,
+    MemoryCacheStore,
+};
+#[cfg(feature = "disk-cache")]
+pub use crate::cache::DiskCacheStore;
+#[cfg(feature = "caching")]
+use crate::cache::{
+    can_spawn,
+    spawn,
+    ApiCache,
+    Lookup,
+    MaybeSend,
+    SharedCacheStore,
+};
+#[cfg(feature = "caching")]
+use serde::Serialize;
+pub use crate::in_flight::DEFAULT_MAX_IN_FLIGHT;
+pub use crate::retry::RetryPolicy;
+#[cfg(not(target_arch = "wasm32"))]
+pub use crate::cassette::{
+    Cassette,
+    CassetteMode,
+    Recording,
+};
 
-const BASE_URL: &str = "https://hacker-news.firebaseio.com/v0";
+/// the official hacker news api
+pub const BASE_URL: &str = "https://hacker-news.firebaseio.com/v0";
 const TOP_STORIES: &str = "/topstories.json";
 const NEW_STORIES: &str = "/newstories.json";
 const BEST_STORIES: &str = "/beststories.json";
@@ -18,168 +70,1051 @@ const JOB_STORIES: &str = "/jobstories.json";
 const ITEM_API: &str = "/item";
 const USER_API: &str = "/user";
 
-const STORIES_COUNT: usize = 20;
-const COMMENT_DEPTH: i64 = 3;
+/// the number of stories in each page of a story listing
+pub const STORIES_COUNT: usize = 20;
+
+/// the time each attempt of a request to the api is allowed to take by default
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(10);
 
-#[cfg(feature = "caching")]
 lazy_static::lazy_static! {
-    static ref STORY_CACHE: Mutex<lru::LruCache<i64, StoryPageData>> =
-        Mutex::new(lru::LruCache::new(1000));
-    static ref STORY_PREVIEW_CACHE: Mutex<lru::LruCache<i64, StoryItem>> =
-        Mutex::new(lru::LruCache::new(1000));
+    static ref DEFAULT_CLIENT: RwLock<HnClient> = RwLock::new(HnClient::default());
+}
+
+/// Replace the client used by the free functions of this module,
+/// ie: to point them to a mirror of the hacker news api.
+pub fn set_default_client(client: HnClient) {
+    *DEFAULT_CLIENT.write().unwrap() = client;
+}
+
+/// return the client used by the free functions of this module
+pub fn default_client() -> HnClient {
+    DEFAULT_CLIENT.read().unwrap().clone()
 }
 
-pub async fn get_stories() -> Result<Vec<StoryItem>, ServerError>{
-    get_stories_with_sorting(StorySorting::default()).await
+pub async fn get_stories() -> Result<StoryList, ServerError> {
+    default_client().get_stories().await
 }
 
 pub async fn get_stories_with_sorting(
     sort: StorySorting,
-) -> Result<Vec<StoryItem>, ServerError> {
-    let stories_api = match sort {
-        StorySorting::Best => BEST_STORIES,
-        StorySorting::Top => TOP_STORIES,
-        StorySorting::New => NEW_STORIES,
-        StorySorting::Show => SHOW_STORIES,
-        StorySorting::Ask => ASK_STORIES,
-        StorySorting::Job => JOB_STORIES,
-    };
-
-    let url = format!("{}{}", BASE_URL, stories_api);
-    let story_ids = make_json_get_request::<Vec<i64>>(&url).await?;
-    println!("story_ids:({}) {:#?}", story_ids.len(), story_ids);
-    let first_story_ids = &story_ids[..story_ids.len().min(STORIES_COUNT)];
-    let story_futures = first_story_ids
-        .iter()
-        .map(|story_id| get_story_preview(*story_id));
-
-    let mut stories = join_all(story_futures)
+    page: usize,
+    order: StoryOrder,
+) -> Result<StoryList, ServerError> {
+    default_client()
+        .get_stories_with_sorting(sort, page, order)
         .await
-        .into_iter()
-        .filter_map(|c| c.ok())
-        .collect::<Vec<_>>();
-
-    stories.sort_unstable_by(|a,b|a.id.cmp(&b.id));
+}
 
-    Ok(stories)
+pub async fn get_item(item_id: i64) -> Result<Item, ServerError> {
+    default_client().get_item(item_id).await
 }
 
 pub async fn get_story(story_id: i64) -> Result<StoryPageData, ServerError> {
+    default_client().get_story(story_id).await
+}
+
+pub async fn get_story_with_options(
+    story_id: i64,
+    options: &CommentFetchOptions,
+) -> Result<StoryPageData, ServerError> {
+    default_client()
+        .get_story_with_options(story_id, options)
+        .await
+}
+
+// Same as get_story but does not add comments
+pub async fn get_story_preview(story_id: i64) -> Result<StoryItem, ServerError> {
+    default_client().get_story_preview(story_id).await
+}
+
+pub async fn get_comment_with_depth(
+    story_id: i64,
+    depth: i64,
+) -> Result<Comment, ServerError> {
+    default_client().get_comment_with_depth(story_id, depth).await
+}
+
+pub async fn get_comment(comment_id: i64) -> Result<Comment, ServerError> {
+    default_client().get_comment(comment_id).await
+}
+
+pub async fn get_comment_with_options(
+    comment_id: i64,
+    options: &CommentFetchOptions,
+) -> Result<Comment, ServerError> {
+    default_client()
+        .get_comment_with_options(comment_id, options)
+        .await
+}
+
+pub async fn get_comment_list_with_options(
+    comment_ids: &[i64],
+    options: &CommentFetchOptions,
+) -> Fetched<Comment> {
+    default_client()
+        .get_comment_list_with_options(comment_ids, options)
+        .await
+}
+
+pub async fn get_user_page(user_id: &str) -> Result<UserData, ServerError> {
+    default_client().get_user_page(user_id).await
+}
+
+pub async fn make_json_get_request<T: serde::de::DeserializeOwned>(
+    url: &str,
+) -> Result<T, ServerError> {
+    default_client().make_json_get_request(url).await
+}
+
+/// A client to the hacker news api.
+///
+/// The underlying `reqwest::Client` and the caches are shared between clones,
+/// so cloning an `HnClient` is cheap and reuses the same connection pool.
+#[derive(Clone, Debug)]
+pub struct HnClient {
+    base_url: String,
+    timeout: Option<Duration>,
+    retry: RetryPolicy,
+    user_agent: Option<String>,
+    http: reqwest::Client,
+    /// read from this snapshot instead of the api
+    snapshot: Option<Arc<Snapshot>>,
+    /// record the responses of the api into, or replay them from, this cassette
+    #[cfg(not(target_arch = "wasm32"))]
+    cassette: Option<Cassette>,
+    /// the requests to the api which are in flight, shared between the clones
+    in_flight: Arc<InFlight>,
+    #[cfg(feature = "caching")]
+    cache: Arc<ApiCache>,
+}
+
+/// Builder for `HnClient`
+#[derive(Clone, Debug)]
+pub struct HnClientBuilder {
+    base_url: String,
+    timeout: Option<Duration>,
+    retry: RetryPolicy,
+    user_agent: Option<String>,
+    snapshot: Option<Snapshot>,
+    #[cfg(not(target_arch = "wasm32"))]
+    cassette: Option<Cassette>,
+    max_in_flight: usize,
+    #[cfg(feature = "caching")]
+    cache_ttl: CacheTtl,
     #[cfg(feature = "caching")]
-    if let Some(cached_story) = STORY_CACHE.lock().unwrap().get(&story_id) {
-        return Ok(cached_story.clone());
+    cache_store: Option<SharedCacheStore>,
+}
+
+impl Default for HnClientBuilder {
+    fn default() -> Self {
+        Self {
+            base_url: BASE_URL.to_string(),
+            timeout: Some(DEFAULT_TIMEOUT),
+            retry: RetryPolicy::default(),
+            user_agent: None,
+            snapshot: None,
+            #[cfg(not(target_arch = "wasm32"))]
+            cassette: None,
+            max_in_flight: DEFAULT_MAX_IN_FLIGHT,
+            #[cfg(feature = "caching")]
+            cache_ttl: CacheTtl::default(),
+            #[cfg(feature = "caching")]
+            cache_store: None,
+        }
     }
+}
 
-    let url = format!("{}{}/{}.json", BASE_URL, ITEM_API, story_id);
-    let mut story = make_json_get_request::<StoryPageData>(&url).await?;
-    let comment_ids = &story.kids[..story.kids.len().min(3)];
-    let comments = join_all(
-        comment_ids
-            .iter()
-            .map(|story_id| get_comment_with_depth(*story_id, COMMENT_DEPTH)),
-    )
-    .await
-    .into_iter()
-    .filter_map(|c| c.ok())
-    .collect();
+impl HnClientBuilder {
+    /// the base url of the api, ie: `https://hacker-news.firebaseio.com/v0`
+    pub fn base_url(mut self, base_url: impl Into<String>) -> Self {
+        self.base_url = base_url.into().trim_end_matches('/').to_string();
+        self
+    }
+
+    /// the maximum time each attempt of a request to the api is allowed to take,
+    /// this is `DEFAULT_TIMEOUT` unless it is set
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// let each attempt of a request to the api take as long as it takes
+    pub fn no_timeout(mut self) -> Self {
+        self.timeout = None;
+        self
+    }
+
+    /// how the requests which failed for a transient reason are retried
+    pub fn retry(mut self, retry: RetryPolicy) -> Self {
+        self.retry = retry;
+        self
+    }
 
-    story.comments = comments;
+    /// the user agent sent with every request.
+    /// This has no effect in wasm, where the browser sets the user agent.
+    pub fn user_agent(mut self, user_agent: impl Into<String>) -> Self {
+        self.user_agent = Some(user_agent.into());
+        self
+    }
+
+    /// read the data from this snapshot instead of the api
+    pub fn snapshot(mut self, snapshot: Snapshot) -> Self {
+        self.snapshot = Some(snapshot);
+        self
+    }
+
+    /// record the responses of the api into, or replay them from, this cassette
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn cassette(mut self, cassette: Cassette) -> Self {
+        self.cassette = Some(cassette);
+        self
+    }
+
+    /// the number of requests to the api which can be in flight at once,
+    /// the other requests wait for their turn
+    pub fn max_in_flight(mut self, max_in_flight: usize) -> Self {
+        self.max_in_flight = max_in_flight;
+        self
+    }
 
+    /// how long each kind of the fetched data is cached
     #[cfg(feature = "caching")]
-    STORY_CACHE.lock().unwrap().put(story_id, story.clone());
+    pub fn cache_ttl(mut self, cache_ttl: CacheTtl) -> Self {
+        self.cache_ttl = cache_ttl;
+        self
+    }
+
+    /// where the fetched data is cached, this is in memory by default
+    #[cfg(feature = "caching")]
+    pub fn cache_store(mut self, cache_store: impl CacheStore + 'static) -> Self {
+        self.cache_store = Some(SharedCacheStore(Arc::new(cache_store)));
+        self
+    }
+
+    pub fn build(self) -> Result<HnClient, ServerError> {
+        #[cfg(not(target_arch = "wasm32"))]
+        let http = {
+            let mut builder = reqwest::Client::builder();
+            if let Some(user_agent) = &self.user_agent {
+                builder = builder.user_agent(user_agent);
+            }
+            builder.build()?
+        };
+        #[cfg(target_arch = "wasm32")]
+        let http = reqwest::Client::builder().build()?;
+
+        // the cached values of another api, or of another snapshot, are not read back
+        #[cfg(feature = "caching")]
+        let cache_namespace = match &self.snapshot {
+            Some(snapshot) => {
+                format!("snapshot@{}", snapshot.created_at.to_rfc3339())
+            }
+            None => self.base_url.clone(),
+        };
 
-    Ok(story)
+        Ok(HnClient {
+            base_url: self.base_url,
+            timeout: self.timeout,
+            retry: self.retry,
+            user_agent: self.user_agent,
+            http,
+            snapshot: self.snapshot.map(Arc::new),
+            #[cfg(not(target_arch = "wasm32"))]
+            cassette: self.cassette,
+            in_flight: Arc::new(InFlight::new(self.max_in_flight)),
+            #[cfg(feature = "caching")]
+            cache: Arc::new(ApiCache::new(
+                self.cache_ttl,
+                self.cache_store
+                    .map(|cache_store| cache_store.0)
+                    .unwrap_or_else(|| Arc::new(MemoryCacheStore::default())),
+                cache_namespace,
+            )),
+        })
+    }
 }
 
-// Same as get_story but does not add comments
-pub async fn get_story_preview(story_id: i64) -> Result<StoryItem, ServerError> {
+impl Default for HnClient {
+    fn default() -> Self {
+        HnClientBuilder::default()
+            .build()
+            .expect("must build a default client")
+    }
+}
+
+impl HnClient {
+    pub fn builder() -> HnClientBuilder {
+        HnClientBuilder::default()
+    }
+
+    /// create a client to the api at this base url with the default settings
+    pub fn new(base_url: impl Into<String>) -> Result<Self, ServerError> {
+        Self::builder().base_url(base_url).build()
+    }
+
+    pub fn base_url(&self) -> &str {
+        &self.base_url
+    }
+
+    pub fn timeout(&self) -> Option<Duration> {
+        self.timeout
+    }
+
+    pub fn retry(&self) -> &RetryPolicy {
+        &self.retry
+    }
+
+    pub fn user_agent(&self) -> Option<&str> {
+        self.user_agent.as_deref()
+    }
+
+    /// the snapshot read instead of the api, if any
+    pub fn snapshot(&self) -> Option<&Snapshot> {
+        self.snapshot.as_deref()
+    }
+
+    pub fn max_in_flight(&self) -> usize {
+        self.in_flight.max_in_flight()
+    }
+
+    /// the cassette the responses of the api are recorded into or replayed from, if any
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn cassette(&self) -> Option<&Cassette> {
+        self.cassette.as_ref()
+    }
+
+    pub async fn get_stories(&self) -> Result<StoryList, ServerError> {
+        self.get_stories_with_sorting(
+            StorySorting::default(),
+            1,
+            StoryOrder::default(),
+        )
+        .await
+    }
+
+    /// get the stories in this page of the listing, the first page is 1.
+    /// The stories are in the ranking order of the listing,
+    /// unless a different `order` is specified which only sorts the stories in this page.
+    pub async fn get_stories_with_sorting(
+        &self,
+        sort: StorySorting,
+        page: usize,
+        order: StoryOrder,
+    ) -> Result<StoryList, ServerError> {
+        let story_ids = self.get_story_ids(sort).await?;
+        let page = page.max(1);
+        let page_ids = story_ids
+            .iter()
+            .skip((page - 1).saturating_mul(STORIES_COUNT))
+            .take(STORIES_COUNT)
+            .copied()
+            .collect::<Vec<_>>();
+
+        let Fetched {
+            items: mut stories,
+            failed,
+            ..
+        } = fetch_all(&page_ids, |story_id| self.get_story_preview(story_id))
+            .await;
+
+        order.sort(&mut stories);
+
+        Ok(StoryList {
+            sorting: sort,
+            page,
+            per_page: STORIES_COUNT,
+            total: story_ids.len(),
+            order,
+            stories,
+            failed,
+        })
+    }
+
+    /// get the ids of the stories in the listing, in their ranking order
+    pub async fn get_story_ids(
+        &self,
+        sort: StorySorting,
+    ) -> Result<Vec<i64>, ServerError> {
+        #[cfg(feature = "caching")]
+        let story_ids = self
+            .cached(
+                |ttl| ttl.story_ids,
+                format!("story_ids/{}", sort.to_str()),
+                move |client| async move { client.fetch_story_ids(sort).await },
+            )
+            .await;
+        #[cfg(not(feature = "caching"))]
+        let story_ids = self.fetch_story_ids(sort).await;
+        story_ids
+    }
+
+    pub(crate) async fn fetch_story_ids(
+        &self,
+        sort: StorySorting,
+    ) -> Result<Vec<i64>, ServerError> {
+        if let Some(snapshot) = &self.snapshot {
+            return snapshot.story_ids(sort);
+        }
+        let stories_api = match sort {
+            StorySorting::Best => BEST_STORIES,
+            StorySorting::Top => TOP_STORIES,
+            StorySorting::New => NEW_STORIES,
+            StorySorting::Show => SHOW_STORIES,
+            StorySorting::Ask => ASK_STORIES,
+            StorySorting::Job => JOB_STORIES,
+        };
+
+        let url = format!("{}{}", self.base_url, stories_api);
+        self.make_json_get_request::<Vec<i64>>(&url).await
+    }
+
+    /// get any item, ie: a story, comment, job, poll or poll option
+    pub async fn get_item(&self, item_id: i64) -> Result<Item, ServerError> {
+        self.fetch_item::<Item>(item_id).await
+    }
+
+    /// fetch the item with this id from the api, or from the snapshot
+    pub(crate) async fn fetch_item<T: DeserializeOwned>(
+        &self,
+        item_id: i64,
+    ) -> Result<T, ServerError> {
+        if let Some(snapshot) = &self.snapshot {
+            return snapshot.item(item_id);
+        }
+        let url = format!("{}{}/{}.json", self.base_url, ITEM_API, item_id);
+        self.make_json_get_request::<T>(&url).await
+    }
+
+    /// fetch the user with this username from the api, or from the snapshot
+    pub(crate) async fn fetch_user<T: DeserializeOwned>(
+        &self,
+        user_id: &str,
+    ) -> Result<T, ServerError> {
+        if let Some(snapshot) = &self.snapshot {
+            return snapshot.user(user_id);
+        }
+        let url = format!("{}{}/{}.json", self.base_url, USER_API, user_id);
+        self.make_json_get_request::<T>(&url).await
+    }
+
+    pub async fn get_story(
+        &self,
+        story_id: i64,
+    ) -> Result<StoryPageData, ServerError> {
+        self.get_story_with_options(story_id, &CommentFetchOptions::default())
+            .await
+    }
+
+    /// get the story and its comment tree, fetched within the limits of `options`
+    pub async fn get_story_with_options(
+        &self,
+        story_id: i64,
+        options: &CommentFetchOptions,
+    ) -> Result<StoryPageData, ServerError> {
+        // only the stories with the default comment tree, or the largest one
+        // which the comments feed is made of, are cached
+        #[cfg(feature = "caching")]
+        {
+            let key = if *options == CommentFetchOptions::default() {
+                Some(format!("story/{}", story_id))
+            } else if *options == CommentFetchOptions::MAX {
+                Some(format!("story/{}/max", story_id))
+            } else {
+                None
+            };
+            if let Some(key) = key {
+                let options = *options;
+                return self
+                    .cached(|ttl| ttl.comments, key, move |client| async move {
+                        client.fetch_story(story_id, &options).await
+                    })
+                    .await;
+            }
+        }
+        self.fetch_story(story_id, options).await
+    }
+
+    async fn fetch_story(
+        &self,
+        story_id: i64,
+        options: &CommentFetchOptions,
+    ) -> Result<StoryPageData, ServerError> {
+        let mut story = self.fetch_item::<StoryPageData>(story_id).await?;
+        // the comments and poll options have no story page of their own
+        if !story.r#type.is_story() {
+            return Err(ServerError::NotFound);
+        }
+        let budget = CommentBudget::new(options.max_total);
+        let comments = self
+            .get_comments(
+                &story.kids,
+                options.max_children_per_level,
+                options,
+                options.max_depth,
+                &budget,
+            )
+            .await;
+        story.comments = comments.items;
+        story.failed = comments.failed;
+        story.not_found_kids = comments.not_found;
+        story.unfetched_kids = story.missing_kids().len();
+        if story.r#type == ItemType::Poll {
+            let poll_options = self.get_poll_options(&story.parts).await;
+            story.poll_options = poll_options.items;
+            story.failed.extend(poll_options.failed);
+        }
+        Ok(story)
+    }
+
+    // Same as get_story but does not add comments
+    pub async fn get_story_preview(
+        &self,
+        story_id: i64,
+    ) -> Result<StoryItem, ServerError> {
+        #[cfg(feature = "caching")]
+        let story_preview = self
+            .cached(
+                |ttl| ttl.items,
+                format!("story_preview/{}", story_id),
+                move |client| async move {
+                    client.fetch_story_preview(story_id).await
+                },
+            )
+            .await;
+        #[cfg(not(feature = "caching"))]
+        let story_preview = self.fetch_story_preview(story_id).await;
+        story_preview
+    }
+
+    async fn fetch_story_preview(
+        &self,
+        story_id: i64,
+    ) -> Result<StoryItem, ServerError> {
+        self.get_item(story_id)
+            .await?
+            .into_story()
+            .ok_or(ServerError::NotFound)
+    }
+
+    /// get the options of a poll, in the order of `parts`
+    async fn get_poll_options(&self, parts: &[i64]) -> Fetched<PollOption> {
+        fetch_all(parts, |part| self.fetch_item::<PollOption>(part)).await
+    }
+
+    /// get the comment and its replies down to `depth` levels
+    pub async fn get_comment_with_depth(
+        &self,
+        comment_id: i64,
+        depth: i64,
+    ) -> Result<Comment, ServerError> {
+        let options = CommentFetchOptions {
+            max_depth: depth.max(0) as usize,
+            ..Default::default()
+        };
+        self.get_comment_with_options(comment_id, &options).await
+    }
+
+    pub async fn get_comment(
+        &self,
+        comment_id: i64,
+    ) -> Result<Comment, ServerError> {
+        self.get_comment_with_options(comment_id, &CommentFetchOptions::default())
+            .await
+    }
+
+    /// get the comment and its replies, fetched within the limits of `options`
+    pub async fn get_comment_with_options(
+        &self,
+        comment_id: i64,
+        options: &CommentFetchOptions,
+    ) -> Result<Comment, ServerError> {
+        // the comment itself counts to the total
+        let budget = CommentBudget::new(options.max_total.saturating_sub(1));
+        self.get_comment_tree(comment_id, options, options.max_depth, &budget)
+            .await
+    }
+
+    /// get these comments along with their replies, fetched within the limits of `options`.
+    /// Unlike the replies, all of `comment_ids` are fetched as long as they are within `max_total`.
+    pub async fn get_comment_list_with_options(
+        &self,
+        comment_ids: &[i64],
+        options: &CommentFetchOptions,
+    ) -> Fetched<Comment> {
+        let budget = CommentBudget::new(options.max_total);
+        self.get_comments(
+            comment_ids,
+            comment_ids.len(),
+            options,
+            options.max_depth,
+            &budget,
+        )
+        .await
+    }
+
+    /// get the first `count` of these comments along with their replies
+    async fn get_comments(
+        &self,
+        comment_ids: &[i64],
+        count: usize,
+        options: &CommentFetchOptions,
+        depth: usize,
+        budget: &CommentBudget,
+    ) -> Fetched<Comment> {
+        let count = budget.reserve(comment_ids.len().min(count));
+        let mut comments = fetch_all(&comment_ids[..count], |comment_id| {
+            self.get_comment_item(comment_id)
+        })
+        .await;
+        self.get_replies(&mut comments.items, options, depth, budget)
+            .await;
+        comments
+    }
+
+    /// get the comment and its replies down to `depth` levels
+    async fn get_comment_tree(
+        &self,
+        comment_id: i64,
+        options: &CommentFetchOptions,
+        depth: usize,
+        budget: &CommentBudget,
+    ) -> Result<Comment, ServerError> {
+        let mut comment = self.get_comment_item(comment_id).await?;
+        self.get_replies(
+            std::slice::from_mut(&mut comment),
+            options,
+            depth,
+            budget,
+        )
+        .await;
+        Ok(comment)
+    }
+
+    /// get the replies of these comments down to `depth` levels, a level at a time.
+    /// The budget of each level is reserved in the order of the comments and their kids
+    /// before the level is fetched, so the replies which are fetched
+    /// do not depend on the order the responses arrive in.
+    #[cfg_attr(target_arch = "wasm32", async_recursion(?Send))]
+    #[cfg_attr(not(target_arch = "wasm32"), async_recursion)]
+    async fn get_replies(
+        &self,
+        comments: &mut [Comment],
+        options: &CommentFetchOptions,
+        depth: usize,
+        budget: &CommentBudget,
+    ) {
+        if depth > 0 {
+            let mut reply_ids = vec![];
+            for comment in comments.iter() {
+                let count = budget.reserve(
+                    comment.kids.len().min(options.max_children_per_level),
+                );
+                reply_ids.extend_from_slice(&comment.kids[..count]);
+            }
+            let Fetched {
+                items: mut replies,
+                failed,
+                not_found,
+            } = fetch_all(&reply_ids, |reply_id| {
+                self.get_comment_item(reply_id)
+            })
+            .await;
+            self.get_replies(&mut replies, options, depth - 1, budget)
+                .await;
+            for comment in comments.iter_mut() {
+                let (sub_comments, rest) = replies
+                    .into_iter()
+                    .partition(|reply| comment.kids.contains(&reply.id));
+                replies = rest;
+                comment.sub_comments = sub_comments;
+                comment.failed = failed
+                    .iter()
+                    .filter(|item| comment.kids.contains(&item.id))
+                    .cloned()
+                    .collect();
+                comment.not_found_kids = not_found
+                    .iter()
+                    .filter(|id| comment.kids.contains(id))
+                    .copied()
+                    .collect();
+            }
+        }
+        for comment in comments.iter_mut() {
+            comment.unfetched_kids = comment.missing_kids().len();
+        }
+    }
+
+    /// get the comment without its replies
+    async fn get_comment_item(
+        &self,
+        comment_id: i64,
+    ) -> Result<Comment, ServerError> {
+        #[cfg(feature = "caching")]
+        let comment = self
+            .cached(
+                |ttl| ttl.comments,
+                format!("comment/{}", comment_id),
+                move |client| async move {
+                    client.fetch_comment_item(comment_id).await
+                },
+            )
+            .await;
+        #[cfg(not(feature = "caching"))]
+        let comment = self.fetch_comment_item(comment_id).await;
+        comment
+    }
+
+    async fn fetch_comment_item(
+        &self,
+        comment_id: i64,
+    ) -> Result<Comment, ServerError> {
+        self.get_item(comment_id)
+            .await?
+            .into_comment()
+            .ok_or(ServerError::NotFound)
+    }
+
+    pub async fn get_user_page(
+        &self,
+        user_id: &str,
+    ) -> Result<UserData, ServerError> {
+        #[cfg(feature = "caching")]
+        let user = {
+            let user_id = user_id.to_string();
+            self.cached(
+                |ttl| ttl.users,
+                format!("user/{}", user_id),
+                move |client| async move { client.fetch_user_page(&user_id).await },
+            )
+            .await
+        };
+        #[cfg(not(feature = "caching"))]
+        let user = self.fetch_user_page(user_id).await;
+        user
+    }
+
+    async fn fetch_user_page(
+        &self,
+        user_id: &str,
+    ) -> Result<UserData, ServerError> {
+        let mut user = self.fetch_user::<UserData>(user_id).await?;
+        //submitted could be comments or story post
+        let first_story_ids = &user.submitted[..user.submitted.len().min(30)];
+        let submitted =
+            fetch_all(first_story_ids, |item_id| self.get_item(item_id)).await;
+
+        // only the stories, jobs and polls are listed, comments and poll options are skipped
+        let stories = submitted
+            .items
+            .into_iter()
+            .filter_map(Item::into_story)
+            .filter(|story| !story.deleted)
+            .collect();
+
+        user.stories = stories;
+        user.failed = submitted.failed;
+        Ok(user)
+    }
+
+    /// get the value cached at `key`, or `fetch` it when it is not cached.
+    /// A stale value is returned right away while it is refreshed in the background.
+    /// The values with some of their items failed to load are not cached,
+    /// so the failed items are fetched again the next time.
     #[cfg(feature = "caching")]
-    if let Some(cached_story) =
-        STORY_PREVIEW_CACHE.lock().unwrap().get(&story_id)
+    async fn cached<V, F, Fut>(
+        &self,
+        ttl: fn(&CacheTtl) -> Duration,
+        key: String,
+        fetch: F,
+    ) -> Result<V, ServerError>
+    where
+        V: Serialize + DeserializeOwned + Complete + MaybeSend + 'static,
+        F: FnOnce(HnClient) -> Fut + MaybeSend,
+        Fut: Future<Output = Result<V, ServerError>> + MaybeSend + 'static,
     {
-        return Ok(cached_story.clone());
+        let ttl = ttl(&self.cache.ttl);
+        match self.cache.get(&key, ttl) {
+            Lookup::Fresh(value) => Ok(value),
+            Lookup::Stale {
+                value,
+                refresh: false,
+            } => Ok(value),
+            Lookup::Stale {
+                value,
+                refresh: true,
+            } => {
+                let refreshed = fetch(self.clone());
+                if can_spawn() {
+                    let api_cache = Arc::clone(&self.cache);
+                    spawn(async move {
+                        match refreshed.await {
+                            Ok(fresh) if fresh.is_complete() => {
+                                api_cache.put(&key, &fresh)
+                            }
+                            _ => api_cache.refresh_failed(&key),
+                        }
+                    });
+                    Ok(value)
+                } else {
+                    // no runtime to refresh in the background, so refresh it now
+                    match refreshed.await {
+                        Ok(fresh) if fresh.is_complete() => {
+                            self.cache.put(&key, &fresh);
+                            Ok(fresh)
+                        }
+                        _ => {
+                            self.cache.refresh_failed(&key);
+                            Ok(value)
+                        }
+                    }
+                }
+            }
+            Lookup::Miss => {
+                let value = fetch(self.clone()).await?;
+                if value.is_complete() {
+                    self.cache.put(&key, &value);
+                }
+                Ok(value)
+            }
+        }
     }
 
-    let url = format!("{}{}/{}.json", BASE_URL, ITEM_API, story_id);
-    let story_preview = make_json_get_request::<StoryItem>(&url).await?;
+    pub async fn make_json_get_request<T: serde::de::DeserializeOwned>(
+        &self,
+        url: &str,
+    ) -> Result<T, ServerError> {
+        let (status, body) = self.get_text(url).await?;
+        if status == reqwest::StatusCode::NOT_FOUND.as_u16() {
+            return Err(ServerError::NotFound);
+        }
+        if !(200..300).contains(&status) {
+            return Err(ServerError::UpstreamStatus(status));
+        }
+        // the api returns `null` for unknown items and users
+        if body.trim() == "null" {
+            return Err(ServerError::NotFound);
+        }
+        Ok(serde_json::from_str::<T>(&body)?)
+    }
 
-    #[cfg(feature = "caching")]
-    STORY_PREVIEW_CACHE
-        .lock()
-        .unwrap()
-        .put(story_id, story_preview.clone());
+    /// the status and the body of the response to this url,
+    /// which is shared with the simultaneous requests to the same url
+    async fn get_text(&self, url: &str) -> Response {
+        let client = self.clone();
+        let request_url = url.to_string();
+        self.in_flight
+            .request(url, move || {
+                Box::pin(async move { client.request_text(&request_url).await })
+            })
+            .await
+    }
+
+    /// request this url, or replay the response from the cassette,
+    /// the response is recorded into the cassette when there is one.
+    /// The request is retried while it fails for a transient reason.
+    async fn request_text(&self, url: &str) -> Response {
+        #[cfg(not(target_arch = "wasm32"))]
+        let path = url.strip_prefix(&self.base_url).unwrap_or(url);
+        #[cfg(not(target_arch = "wasm32"))]
+        if let Some(cassette) = &self.cassette {
+            if cassette.mode() == CassetteMode::Replay {
+                let recording = cassette.load(path)?;
+                return Ok((recording.status, recording.body));
+            }
+        }
+        let mut retry = 0;
+        let (status, body) = loop {
+            let response = self.attempt(url).await;
+            let transient = match &response {
+                Ok((status, _)) => is_transient_status(*status),
+                Err(e) => e.is_transient(),
+            };
+            if !transient || retry >= self.retry.max_retries {
+                break response?;
+            }
+            sleep(self.retry.delay(retry)).await;
+            retry += 1;
+        };
+        #[cfg(not(target_arch = "wasm32"))]
+        if let Some(cassette) = &self.cassette {
+            if cassette.mode() == CassetteMode::Record {
+                let recording = Recording {
+                    url: url.to_string(),
+                    status,
+                    body,
+                };
+                cassette.save(path, &recording)?;
+                return Ok((recording.status, recording.body));
+            }
+        }
+        Ok((status, body))
+    }
 
-    Ok(story_preview)
+    /// request this url once its turn comes, within the timeout
+    async fn attempt(&self, url: &str) -> Response {
+        let _permit = self.in_flight.permit().await;
+        with_timeout(self.timeout, async {
+            let response = self.http.get(url).send().await?;
+            Ok((response.status().as_u16(), response.text().await?))
+        })
+        .await
+    }
 }
 
+/// fetch the items with these ids all at once, keeping the ones which failed to load.
+/// The items which do not exist, ie: a `null` kid, are kept apart in `not_found`
+/// rather than reported as failed, since fetching them again does not bring them back.
+async fn fetch_all<T, F, Fut>(ids: &[i64], fetch: F) -> Fetched<T>
+where
+    F: Fn(i64) -> Fut,
+    Fut: Future<Output = Result<T, ServerError>>,
+{
+    let results = join_all(ids.iter().map(|id| fetch(*id))).await;
+    let mut fetched = Fetched::default();
+    for (id, result) in ids.iter().zip(results) {
+        match result {
+            Ok(item) => fetched.items.push(item),
+            Err(ServerError::NotFound) => fetched.not_found.push(*id),
+            Err(e) => fetched.failed.push(FailedItem {
+                id: *id,
+                code: e.code().to_string(),
+            }),
+        }
+    }
+    fetched
+}
 
+/// Whether all of the items in a fetched value were loaded
+#[cfg(feature = "caching")]
+trait Complete {
+    fn is_complete(&self) -> bool;
+}
 
-#[cfg_attr(target_arch = "wasm32", async_recursion(?Send))]
-#[cfg_attr(not(target_arch = "wasm32"), async_recursion)]
-pub async fn get_comment_with_depth(
-    story_id: i64,
-    depth: i64,
-) -> Result<Comment, ServerError> {
-    let url = format!("{}{}/{}.json", BASE_URL, ITEM_API, story_id);
-    let mut comment = make_json_get_request::<Comment>(&url).await?;
-    if depth > 0 {
-        let sub_comment_ids = &comment.kids[..comment.kids.len().min(3)];
-        let sub_comments = join_all(
-            sub_comment_ids
-                .iter()
-                .map(|story_id| get_comment_with_depth(*story_id, depth - 1)),
-        )
-        .await
-        .into_iter()
-        .filter_map(|c| c.ok())
-        .collect();
+#[cfg(feature = "caching")]
+impl Complete for Vec<i64> {
+    fn is_complete(&self) -> bool {
+        true
+    }
+}
 
-        comment.sub_comments = sub_comments;
+#[cfg(feature = "caching")]
+impl Complete for StoryItem {
+    fn is_complete(&self) -> bool {
+        true
     }
-    Ok(comment)
 }
 
-pub async fn get_comment(comment_id: i64) -> Result<Comment, ServerError> {
-    let comment = get_comment_with_depth(comment_id, COMMENT_DEPTH).await?;
-    Ok(comment)
+#[cfg(feature = "caching")]
+impl Complete for StoryPageData {
+    fn is_complete(&self) -> bool {
+        self.failed_count() == 0
+    }
 }
 
+#[cfg(feature = "caching")]
+impl Complete for Comment {
+    fn is_complete(&self) -> bool {
+        self.failed_count() == 0
+    }
+}
 
-pub async fn get_user_page(user_id: &str) -> Result<UserData, ServerError> {
-    let url = format!("{}{}/{}.json", BASE_URL, USER_API, user_id);
-    let mut user = make_json_get_request::<UserData>(&url).await?;
-    //submitted could be comments or story post
-    let first_story_ids = &user.submitted[..user.submitted.len().min(30)];
-    let story_futures = first_story_ids
-        .iter()
-        .map(|story_id| get_story_preview(*story_id));
-
-    // we only filter the success where other types such as comments fail
-    let stories = join_all(story_futures)
-        .await
-        .into_iter()
-        .filter_map(|story| story.ok())
-        .collect();
+#[cfg(feature = "caching")]
+impl Complete for UserData {
+    fn is_complete(&self) -> bool {
+        self.failed.is_empty()
+    }
+}
+
+/// The number of comments that can still be fetched in a comment tree,
+/// which is reserved a level of the tree at a time, in the order of the kids.
+struct CommentBudget(AtomicUsize);
 
-    user.stories = stories;
+impl CommentBudget {
+    fn new(total: usize) -> Self {
+        Self(AtomicUsize::new(total))
+    }
 
-    dbg!(&user);
-    Ok(user)
+    /// take up to `wanted` comments from the budget, returning how many were taken
+    fn reserve(&self, wanted: usize) -> usize {
+        let mut reserved = 0;
+        let _ = self
+            .0
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |left| {
+                reserved = left.min(wanted);
+                Some(left - reserved)
+            });
+        reserved
+    }
 }
 
-#[derive(Error, Debug)]
+/// The errors are cloned to each of the simultaneous requests to the same url,
+/// so the underlying errors which can not be cloned are shared
+#[derive(Error, Debug, Clone)]
 pub enum ServerError {
+    /// the item or user does not exist
+    #[error("not found")]
+    NotFound,
+    /// the api did not respond in time
+    #[error("request timed out")]
+    Timeout,
+    /// the api responded with an unsuccessful http status
+    #[error("upstream responded with status: {0}")]
+    UpstreamStatus(u16),
+    /// the api responded with a body that is not the expected json
+    #[error("decode error: {0}")]
+    Decode(Arc<serde_json::Error>),
     #[error("reqwest error: {0}")]
-    Reqwest(#[from] reqwest::Error),
-    #[error("json error: {0}")]
-    SerdeJson(#[from] serde_json::Error),
+    Reqwest(Arc<reqwest::Error>),
+    /// the request is not in the cassette which is replayed
+    #[error("no recorded response to: {0}")]
+    NotRecorded(String),
+    /// the response can not be written into the cassette which is recorded
+    #[error("unable to record: {0}")]
+    RecordFailed(String),
 }
 
+impl ServerError {
+    /// a stable code of the kind of the error, used in the json api
+    pub fn code(&self) -> &'static str {
+        match self {
+            ServerError::NotFound => "not_found",
+            ServerError::Timeout => "timeout",
+            ServerError::UpstreamStatus(_) => "upstream_status",
+            ServerError::Decode(_) => "decode_error",
+            ServerError::Reqwest(_) => "request_error",
+            ServerError::NotRecorded(_) => "not_recorded",
+            ServerError::RecordFailed(_) => "record_failed",
+        }
+    }
 
-pub async fn make_json_get_request<T: serde::de::DeserializeOwned>(
-    url: &str,
-) -> Result<T, ServerError> {
-    dbg!(url);
-    let response = reqwest::get(url).await?;
-    Ok(response.json::<T>().await?)
+    /// whether the request is likely to succeed when it is made again
+    pub fn is_transient(&self) -> bool {
+        match self {
+            ServerError::Timeout => true,
+            ServerError::UpstreamStatus(status) => is_transient_status(*status),
+            ServerError::Reqwest(e) => !e.is_builder(),
+            ServerError::NotFound
+            | ServerError::Decode(_)
+            | ServerError::NotRecorded(_)
+            | ServerError::RecordFailed(_) => false,
+        }
+    }
+}
+
+impl From<reqwest::Error> for ServerError {
+    fn from(e: reqwest::Error) -> Self {
+        if e.is_timeout() {
+            ServerError::Timeout
+        } else if let Some(status) = e.status() {
+            ServerError::UpstreamStatus(status.as_u16())
+        } else {
+            ServerError::Reqwest(Arc::new(e))
+        }
+    }
+}
+
+impl From<serde_json::Error> for ServerError {
+    fn from(e: serde_json::Error) -> Self {
+        ServerError::Decode(Arc::new(e))
+    }
 }
@@ -0,0 +1,373 @@
+//! Time-aware caches of the data fetched from the hacker news api.
+//!
+//! Each cached value remembers when it was fetched. A value older than its ttl is stale,
+//! it is still served for a while but gets refreshed in the background.
+//!
+//! The values are stored as json in a [`CacheStore`], which is the in-memory [`MemoryCacheStore`]
+//! by default, or the on-disk [`DiskCacheStore`] with the `disk-cache` feature.
+//! The keys in the store are namespaced by the api the values are fetched from,
+//! so a store which outlives the server is not read back by a server of another api.
+use chrono::{
+    DateTime,
+    Utc,
+};
+use serde::{
+    de::DeserializeOwned,
+    Deserialize,
+    Serialize,
+};
+#[cfg(feature = "disk-cache")]
+use std::sync::atomic::{
+    AtomicUsize,
+    Ordering,
+};
+use std::{
+    collections::HashSet,
+    fmt,
+    future::Future,
+    sync::{
+        Arc,
+        Mutex,
+    },
+    time::Duration,
+};
+
+const CACHE_CAPACITY: usize = 5000;
+
+/// the entries kept on disk by default
+#[cfg(feature = "disk-cache")]
+const DISK_CACHE_CAPACITY: usize = 100_000;
+
+/// how long the entries are kept on disk by default,
+/// well past the ttl of any of the cached values
+#[cfg(feature = "disk-cache")]
+const DISK_CACHE_MAX_AGE: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// the writes to the disk cache after which the old and the excess entries are swept
+#[cfg(feature = "disk-cache")]
+const SWEEP_INTERVAL: usize = 1000;
+
+/// How long the cached data of each kind is fresh
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct CacheTtl {
+    /// the story ids of the story listings
+    pub story_ids: Duration,
+    /// the story previews
+    pub items: Duration,
+    /// the comments, including the comment tree of the story pages
+    pub comments: Duration,
+    /// the user pages
+    pub users: Duration,
+    /// how long after its ttl a stale value is still served while it is refreshed
+    pub stale: Duration,
+}
+
+impl Default for CacheTtl {
+    fn default() -> Self {
+        Self {
+            story_ids: Duration::from_secs(60),
+            items: Duration::from_secs(5 * 60),
+            comments: Duration::from_secs(2 * 60),
+            users: Duration::from_secs(10 * 60),
+            stale: Duration::from_secs(60 * 60),
+        }
+    }
+}
+
+/// A cached value serialized as json, along with the time it was fetched
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct CacheEntry {
+    pub value: String,
+    pub fetched_at: DateTime<Utc>,
+}
+
+/// A storage of the cache entries
+pub trait CacheStore: Send + Sync {
+    fn get(&self, key: &str) -> Option<CacheEntry>;
+
+    fn put(&self, key: &str, entry: CacheEntry);
+
+    fn remove(&self, key: &str);
+}
+
+/// Stores the cache entries in memory, evicting the least recently used entries
+pub struct MemoryCacheStore {
+    entries: Mutex<lru::LruCache<String, CacheEntry>>,
+}
+
+impl MemoryCacheStore {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            entries: Mutex::new(lru::LruCache::new(capacity)),
+        }
+    }
+}
+
+impl Default for MemoryCacheStore {
+    fn default() -> Self {
+        Self::new(CACHE_CAPACITY)
+    }
+}
+
+impl CacheStore for MemoryCacheStore {
+    fn get(&self, key: &str) -> Option<CacheEntry> {
+        self.entries.lock().unwrap().get(&key.to_string()).cloned()
+    }
+
+    fn put(&self, key: &str, entry: CacheEntry) {
+        self.entries.lock().unwrap().put(key.to_string(), entry);
+    }
+
+    fn remove(&self, key: &str) {
+        self.entries.lock().unwrap().pop(&key.to_string());
+    }
+}
+
+/// The cache store shared by the clients built from the same builder
+#[derive(Clone)]
+pub(crate) struct SharedCacheStore(pub(crate) Arc<dyn CacheStore>);
+
+impl fmt::Debug for SharedCacheStore {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("SharedCacheStore").finish()
+    }
+}
+
+/// Stores the cache entries in an embedded database on disk,
+/// so the cache survives restarts of the server.
+///
+/// Every so often, the entries older than `max_age` are removed,
+/// and then the oldest entries past the `capacity`.
+#[cfg(feature = "disk-cache")]
+pub struct DiskCacheStore {
+    db: sled::Db,
+    capacity: usize,
+    max_age: Duration,
+    /// the writes since the last sweep
+    writes: AtomicUsize,
+}
+
+#[cfg(feature = "disk-cache")]
+impl DiskCacheStore {
+    /// open or create the database in this directory
+    pub fn open(path: impl AsRef<std::path::Path>) -> Result<Self, sled::Error> {
+        let store = Self {
+            db: sled::open(path)?,
+            capacity: DISK_CACHE_CAPACITY,
+            max_age: DISK_CACHE_MAX_AGE,
+            writes: AtomicUsize::new(0),
+        };
+        // the entries left by the previous runs
+        store.sweep();
+        Ok(store)
+    }
+
+    /// the number of entries kept on disk
+    pub fn capacity(mut self, capacity: usize) -> Self {
+        self.capacity = capacity;
+        self
+    }
+
+    /// how long the entries are kept on disk
+    pub fn max_age(mut self, max_age: Duration) -> Self {
+        self.max_age = max_age;
+        self
+    }
+
+    /// the number of entries on disk
+    pub fn len(&self) -> usize {
+        self.db.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.db.is_empty()
+    }
+
+    /// remove the entries older than `max_age`, and then the oldest entries past the `capacity`
+    pub fn sweep(&self) {
+        let now = Utc::now();
+        let mut kept = vec![];
+        for (key, bytes) in self.db.iter().flatten() {
+            let entry = serde_json::from_slice::<CacheEntry>(&bytes).ok();
+            let fresh = entry.as_ref().map(|entry| {
+                (now - entry.fetched_at).to_std().unwrap_or_default()
+                    <= self.max_age
+            });
+            match (entry, fresh) {
+                (Some(entry), Some(true)) => kept.push((entry.fetched_at, key)),
+                // too old, or written by an incompatible version
+                _ => {
+                    let _ = self.db.remove(key);
+                }
+            }
+        }
+        if kept.len() > self.capacity {
+            kept.sort();
+            let excess = kept.len() - self.capacity;
+            for (_, key) in kept.into_iter().take(excess) {
+                let _ = self.db.remove(key);
+            }
+        }
+    }
+}
+
+#[cfg(feature = "disk-cache")]
+impl CacheStore for DiskCacheStore {
+    fn get(&self, key: &str) -> Option<CacheEntry> {
+        let bytes = self.db.get(key).ok()??;
+        serde_json::from_slice(&bytes).ok()
+    }
+
+    fn put(&self, key: &str, entry: CacheEntry) {
+        // the cache is best-effort, a failed write is only a cache miss later
+        if let Ok(bytes) = serde_json::to_vec(&entry) {
+            let _ = self.db.insert(key, bytes);
+        }
+        if self.writes.fetch_add(1, Ordering::Relaxed) + 1 >= SWEEP_INTERVAL {
+            self.writes.store(0, Ordering::Relaxed);
+            self.sweep();
+        }
+    }
+
+    fn remove(&self, key: &str) {
+        let _ = self.db.remove(key);
+    }
+}
+
+/// The result of looking up a value in the cache
+pub(crate) enum Lookup<V> {
+    /// the value is within its ttl
+    Fresh(V),
+    /// the value is past its ttl, `refresh` is true for the one lookup
+    /// which is responsible to refresh it
+    Stale { value: V, refresh: bool },
+    Miss,
+}
+
+/// The cache of the data fetched from the api
+pub(crate) struct ApiCache {
+    pub(crate) ttl: CacheTtl,
+    store: Arc<dyn CacheStore>,
+    /// the prefix of the keys in the store, which tells apart the apis sharing a store
+    namespace: String,
+    /// the keys of the stale values which are being refreshed
+    refreshing: Mutex<HashSet<String>>,
+}
+
+impl ApiCache {
+    pub(crate) fn new(
+        ttl: CacheTtl,
+        store: Arc<dyn CacheStore>,
+        namespace: String,
+    ) -> Self {
+        Self {
+            ttl,
+            store,
+            namespace,
+            refreshing: Mutex::new(HashSet::new()),
+        }
+    }
+
+    /// the key of the value in the store, ie: `https://hacker-news.firebaseio.com/v0#story/8863`
+    fn store_key(&self, key: &str) -> String {
+        format!("{}#{}", self.namespace, key)
+    }
+
+    pub(crate) fn get<V>(&self, key: &str, ttl: Duration) -> Lookup<V>
+    where
+        V: DeserializeOwned,
+    {
+        let store_key = self.store_key(key);
+        let entry = match self.store.get(&store_key) {
+            Some(entry) => entry,
+            None => return Lookup::Miss,
+        };
+        let age = (Utc::now() - entry.fetched_at)
+            .to_std()
+            .unwrap_or_default();
+        let value = match serde_json::from_str(&entry.value) {
+            Ok(value) if age <= ttl + self.ttl.stale => value,
+            // expired, or written by an incompatible version
+            _ => {
+                self.store.remove(&store_key);
+                return Lookup::Miss;
+            }
+        };
+        if age <= ttl {
+            Lookup::Fresh(value)
+        } else {
+            let refresh =
+                self.refreshing.lock().unwrap().insert(key.to_string());
+            Lookup::Stale { value, refresh }
+        }
+    }
+
+    pub(crate) fn put<V>(&self, key: &str, value: &V)
+    where
+        V: Serialize,
+    {
+        if let Ok(value) = serde_json::to_string(value) {
+            self.store.put(
+                &self.store_key(key),
+                CacheEntry {
+                    value,
+                    fetched_at: Utc::now(),
+                },
+            );
+        }
+        self.refreshing.lock().unwrap().remove(key);
+    }
+
+    /// allow the stale value to be refreshed again after a failed refresh
+    pub(crate) fn refresh_failed(&self, key: &str) {
+        self.refreshing.lock().unwrap().remove(key);
+    }
+}
+
+impl fmt::Debug for ApiCache {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ApiCache")
+            .field("ttl", &self.ttl)
+            .field("namespace", &self.namespace)
+            .finish()
+    }
+}
+
+/// Futures are required to be `Send` except in wasm, where they can not be
+#[cfg(not(target_arch = "wasm32"))]
+pub(crate) trait MaybeSend: Send {}
+#[cfg(not(target_arch = "wasm32"))]
+impl<T: Send> MaybeSend for T {}
+
+#[cfg(target_arch = "wasm32")]
+pub(crate) trait MaybeSend {}
+#[cfg(target_arch = "wasm32")]
+impl<T> MaybeSend for T {}
+
+/// whether a background task can be spawned in this context
+#[cfg(not(target_arch = "wasm32"))]
+pub(crate) fn can_spawn() -> bool {
+    tokio::runtime::Handle::try_current().is_ok()
+}
+
+#[cfg(target_arch = "wasm32")]
+pub(crate) fn can_spawn() -> bool {
+    true
+}
+
+/// run the future in the background
+#[cfg(not(target_arch = "wasm32"))]
+pub(crate) fn spawn<F>(future: F)
+where
+    F: Future<Output = ()> + MaybeSend + 'static,
+{
+    tokio::spawn(future);
+}
+
+#[cfg(target_arch = "wasm32")]
+pub(crate) fn spawn<F>(future: F)
+where
+    F: Future<Output = ()> + MaybeSend + 'static,
+{
+    wasm_bindgen_futures::spawn_local(future);
+}
@@ -0,0 +1,106 @@
+//! Record the responses of the hacker news api into a cassette directory,
+//! and replay them from it later, so a bug seen on the live site can be reproduced offline.
+//!
+//! Each response is a json file named after the url of the request relative to the base url,
+//! ie: `item/8863.json` is recorded into `item_8863.json`, so a cassette can be replayed
+//! with a client to any base url.
+use crate::api::ServerError;
+use serde::{
+    Deserialize,
+    Serialize,
+};
+use std::path::{
+    Path,
+    PathBuf,
+};
+
+/// Whether the responses are written to or read from the cassette
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum CassetteMode {
+    /// request the api and write every response to the cassette
+    Record,
+    /// read the responses from the cassette instead of requesting the api
+    Replay,
+}
+
+/// A directory of recorded responses of the api
+#[derive(Clone, Debug)]
+pub struct Cassette {
+    dir: PathBuf,
+    mode: CassetteMode,
+}
+
+/// A response of the api as it is recorded in the cassette
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Recording {
+    /// the url which was requested
+    pub url: String,
+    pub status: u16,
+    /// the body of the response, exactly as it was received
+    pub body: String,
+}
+
+impl Cassette {
+    /// record the responses of the api into this directory
+    pub fn record(dir: impl Into<PathBuf>) -> Self {
+        Self {
+            dir: dir.into(),
+            mode: CassetteMode::Record,
+        }
+    }
+
+    /// replay the responses recorded in this directory
+    pub fn replay(dir: impl Into<PathBuf>) -> Self {
+        Self {
+            dir: dir.into(),
+            mode: CassetteMode::Replay,
+        }
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    pub fn mode(&self) -> CassetteMode {
+        self.mode
+    }
+
+    /// the file of the recording of this path, relative to the base url
+    fn file(&self, path: &str) -> PathBuf {
+        let name = path
+            .trim_start_matches('/')
+            .chars()
+            .map(|c| {
+                if c.is_ascii_alphanumeric() || c == '.' || c == '-' {
+                    c
+                } else {
+                    '_'
+                }
+            })
+            .collect::<String>();
+        self.dir.join(name)
+    }
+
+    /// write the recording of the response to the request of this path.
+    /// A recording which can not be written fails the request,
+    /// rather than leaving a cassette which is missing some of the responses.
+    pub(crate) fn save(
+        &self,
+        path: &str,
+        recording: &Recording,
+    ) -> Result<(), ServerError> {
+        let json = serde_json::to_string_pretty(recording).expect("must serialize");
+        std::fs::create_dir_all(&self.dir)
+            .and_then(|_| std::fs::write(self.file(path), json))
+            .map_err(|e| {
+                ServerError::RecordFailed(format!("{}: {}", recording.url, e))
+            })
+    }
+
+    /// read the recording of the response to the request of this path
+    pub(crate) fn load(&self, path: &str) -> Result<Recording, ServerError> {
+        let json = std::fs::read_to_string(self.file(path))
+            .map_err(|_| ServerError::NotRecorded(path.to_string()))?;
+        Ok(serde_json::from_str(&json)?)
+    }
+}
@@ -0,0 +1,108 @@
+//! The requests to the hacker news api which are in flight.
+//!
+//! A page can fan out to dozens of requests, so only a limited number of them are
+//! in flight at once and the rest wait for their turn. The simultaneous requests to the same url,
+//! ie: the same item requested by concurrent visitors, share a single request.
+//!
+//! Only the callers hold on to a shared request, so it is dropped, along with its turn,
+//! as soon as all of them are dropped, ie: when the visitors went away mid-request.
+use crate::api::ServerError;
+use async_lock::{
+    Semaphore,
+    SemaphoreGuard,
+};
+use futures::future::{
+    FutureExt,
+    WeakShared,
+};
+use std::{
+    collections::HashMap,
+    fmt,
+    future::Future,
+    pin::Pin,
+    sync::Mutex,
+};
+
+/// the requests which are in flight at once by default
+pub const DEFAULT_MAX_IN_FLIGHT: usize = 16;
+
+/// the status and the body of a response of the api
+pub(crate) type Response = Result<(u16, String), ServerError>;
+
+/// Futures are required to be `Send` except in wasm, where they can not be
+#[cfg(not(target_arch = "wasm32"))]
+pub(crate) type ResponseFuture =
+    Pin<Box<dyn Future<Output = Response> + Send + 'static>>;
+#[cfg(target_arch = "wasm32")]
+pub(crate) type ResponseFuture = Pin<Box<dyn Future<Output = Response> + 'static>>;
+
+pub(crate) struct InFlight {
+    max_in_flight: usize,
+    permits: Semaphore,
+    /// the requests by their url, shared by the simultaneous requests to the same url.
+    /// These are weak, so a request which all its callers gave up on is not kept running.
+    requests: Mutex<HashMap<String, WeakShared<ResponseFuture>>>,
+}
+
+impl InFlight {
+    pub(crate) fn new(max_in_flight: usize) -> Self {
+        let max_in_flight = max_in_flight.max(1);
+        Self {
+            max_in_flight,
+            permits: Semaphore::new(max_in_flight),
+            requests: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub(crate) fn max_in_flight(&self) -> usize {
+        self.max_in_flight
+    }
+
+    /// wait for a turn to make a request, which lasts until the guard is dropped
+    pub(crate) async fn permit(&self) -> SemaphoreGuard<'_> {
+        self.permits.acquire().await
+    }
+
+    /// the response to the request of this url, which is made with `request`
+    /// unless the same url is already in flight, in which case its response is shared
+    pub(crate) async fn request(
+        &self,
+        url: &str,
+        request: impl FnOnce() -> ResponseFuture,
+    ) -> Response {
+        let shared = {
+            let mut requests = self.requests.lock().expect("must lock");
+            match requests.get(url).and_then(WeakShared::upgrade) {
+                Some(shared) => shared,
+                None => {
+                    // forget the requests which were given up on
+                    requests.retain(|_, weak| weak.upgrade().is_some());
+                    let shared = request().shared();
+                    let weak = shared.downgrade().expect("must not be complete");
+                    requests.insert(url.to_string(), weak);
+                    shared
+                }
+            }
+        };
+        let response = shared.clone().await;
+        let mut requests = self.requests.lock().expect("must lock");
+        // a newer request to the same url may have taken its place already
+        if requests
+            .get(url)
+            .and_then(WeakShared::upgrade)
+            .map(|in_flight| in_flight.ptr_eq(&shared))
+            .unwrap_or(false)
+        {
+            requests.remove(url);
+        }
+        response
+    }
+}
+
+impl fmt::Debug for InFlight {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("InFlight")
+            .field("max_in_flight", &self.max_in_flight)
+            .finish()
+    }
+}
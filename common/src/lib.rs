@@ -1,4 +1,14 @@
 #![deny(warnings)]
 #![deny(unused_extern_crates)]
 pub mod api;
+#[cfg(feature = "caching")]
+mod cache;
+#[cfg(not(target_arch = "wasm32"))]
+mod cassette;
+mod in_flight;
+#[cfg(feature = "openapi")]
+pub mod openapi;
+mod retry;
+pub mod route;
+pub mod snapshot;
 pub mod types;
@@ -0,0 +1,138 @@
+//! The OpenAPI 3 description of the json api of the server,
+//! with the schemas derived from the types the api responds with.
+use crate::route::API_V1;
+use crate::types::{
+    ApiResponse,
+    Comment,
+    StoryItem,
+    StoryOrder,
+    StoryPageData,
+    StorySorting,
+    UserData,
+};
+use schemars::{
+    gen::{
+        SchemaGenerator,
+        SchemaSettings,
+    },
+    JsonSchema,
+};
+use serde_json::{
+    json,
+    Map,
+    Value,
+};
+
+/// the OpenAPI 3 document of the json api
+pub fn openapi() -> Value {
+    let mut gen = SchemaSettings::openapi3().into_generator();
+    let error = response_of::<ApiResponse<()>>(&mut gen, "the error");
+    let order = gen.subschema_for::<StoryOrder>();
+
+    let mut paths = Map::new();
+    for sorting in StorySorting::all() {
+        paths.insert(
+            format!("{}/{}", API_V1, sorting.to_str()),
+            json!({
+                "get": {
+                    "summary": format!("a page of the {} stories", sorting.to_str()),
+                    "parameters": [
+                        query_param("p", json!({"type": "integer", "minimum": 1}), "the page number, starting at 1"),
+                        query_param("sort", json!(order), "the order of the stories in the page, ranked by default"),
+                    ],
+                    "responses": {
+                        "200": response_of::<ApiResponse<Vec<StoryItem>>>(&mut gen, "the stories in the page"),
+                        "default": error,
+                    },
+                },
+            }),
+        );
+    }
+    paths.insert(
+        format!("{}/item/{{story_id}}", API_V1),
+        json!({
+            "get": {
+                "summary": "the story and its comments",
+                "parameters": [
+                    path_param("story_id", json!({"type": "integer"})),
+                    query_param("depth", json!({"type": "integer"}), "the levels of replies fetched"),
+                    query_param("children", json!({"type": "integer"}), "the replies fetched per comment"),
+                    query_param("total", json!({"type": "integer"}), "the comments fetched in total"),
+                ],
+                "responses": {
+                    "200": response_of::<ApiResponse<StoryPageData>>(&mut gen, "the story"),
+                    "default": error,
+                },
+            },
+        }),
+    );
+    paths.insert(
+        format!("{}/comment/{{comment_id}}", API_V1),
+        json!({
+            "get": {
+                "summary": "the comment and its replies",
+                "parameters": [path_param("comment_id", json!({"type": "integer"}))],
+                "responses": {
+                    "200": response_of::<ApiResponse<Comment>>(&mut gen, "the comment"),
+                    "default": error,
+                },
+            },
+        }),
+    );
+    paths.insert(
+        format!("{}/user/{{username}}", API_V1),
+        json!({
+            "get": {
+                "summary": "the user and the stories submitted",
+                "parameters": [path_param("username", json!({"type": "string"}))],
+                "responses": {
+                    "200": response_of::<ApiResponse<UserData>>(&mut gen, "the user"),
+                    "default": error,
+                },
+            },
+        }),
+    );
+
+    json!({
+        "openapi": "3.0.3",
+        "info": {
+            "title": "Hacker News Sauron",
+            "version": env!("CARGO_PKG_VERSION"),
+        },
+        "paths": paths,
+        "components": {
+            "schemas": gen.definitions(),
+        },
+    })
+}
+
+/// the json response with the schema of `T`
+fn response_of<T: JsonSchema>(gen: &mut SchemaGenerator, description: &str) -> Value {
+    json!({
+        "description": description,
+        "content": {
+            "application/json": {
+                "schema": gen.subschema_for::<T>(),
+            },
+        },
+    })
+}
+
+fn query_param(name: &str, schema: Value, description: &str) -> Value {
+    json!({
+        "name": name,
+        "in": "query",
+        "required": false,
+        "description": description,
+        "schema": schema,
+    })
+}
+
+fn path_param(name: &str, schema: Value) -> Value {
+    json!({
+        "name": name,
+        "in": "path",
+        "required": true,
+        "schema": schema,
+    })
+}
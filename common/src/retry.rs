@@ -0,0 +1,109 @@
+//! Retry of the requests to the hacker news api which failed for a transient reason,
+//! ie: a timeout or a 503, with an exponential backoff.
+//!
+//! The backoff is jittered, so the retries of the many requests which failed at once,
+//! ie: all the stories of a page, are spread out instead of hitting the api all at once again.
+use crate::api::ServerError;
+use futures::{
+    future::{
+        select,
+        Either,
+    },
+    pin_mut,
+};
+use std::{
+    collections::hash_map::RandomState,
+    future::Future,
+    hash::{
+        BuildHasher,
+        Hasher,
+    },
+    sync::atomic::{
+        AtomicU64,
+        Ordering,
+    },
+    time::Duration,
+};
+
+/// How the requests which failed for a transient reason are retried
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct RetryPolicy {
+    /// the retries after the first attempt, no retry is made when this is 0
+    pub max_retries: u32,
+    /// the backoff before the first retry, which is doubled for each retry after it
+    pub base_delay: Duration,
+    /// the longest backoff before a retry
+    pub max_delay: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_retries: 2,
+            base_delay: Duration::from_millis(100),
+            max_delay: Duration::from_secs(2),
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// do not retry the requests
+    pub fn none() -> Self {
+        Self {
+            max_retries: 0,
+            ..Default::default()
+        }
+    }
+
+    /// the backoff before this retry, the first retry is 0.
+    /// It is between half and all of the exponential backoff.
+    pub fn delay(&self, retry: u32) -> Duration {
+        let backoff = self
+            .base_delay
+            .saturating_mul(2u32.saturating_pow(retry))
+            .min(self.max_delay);
+        backoff / 2 + backoff.mul_f64(jitter() / 2.0)
+    }
+}
+
+/// whether the response with this status is likely to succeed when it is requested again
+pub(crate) fn is_transient_status(status: u16) -> bool {
+    status == 429 || (500..600).contains(&status)
+}
+
+/// a random number in `0.0..1.0`
+fn jitter() -> f64 {
+    static COUNTER: AtomicU64 = AtomicU64::new(0);
+    let mut hasher = RandomState::new().build_hasher();
+    hasher.write_u64(COUNTER.fetch_add(1, Ordering::Relaxed));
+    (hasher.finish() >> 11) as f64 / (1u64 << 53) as f64
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+pub(crate) async fn sleep(duration: Duration) {
+    tokio::time::sleep(duration).await
+}
+
+#[cfg(target_arch = "wasm32")]
+pub(crate) async fn sleep(duration: Duration) {
+    gloo_timers::future::sleep(duration).await
+}
+
+/// the output of the future, or a timeout error when it takes longer than `timeout`
+pub(crate) async fn with_timeout<T>(
+    timeout: Option<Duration>,
+    future: impl Future<Output = Result<T, ServerError>>,
+) -> Result<T, ServerError> {
+    match timeout {
+        Some(timeout) => {
+            let expired = sleep(timeout);
+            pin_mut!(future);
+            pin_mut!(expired);
+            match select(future, expired).await {
+                Either::Left((output, _)) => output,
+                Either::Right(_) => Err(ServerError::Timeout),
+            }
+        }
+        None => future.await,
+    }
+}
@@ -0,0 +1,268 @@
+//! The routes of the pages in the app, shared by the server and the client.
+use crate::types::{
+    CommentFetchOptions,
+    StorySorting,
+};
+use percent_encoding::{
+    percent_decode_str,
+    utf8_percent_encode,
+    AsciiSet,
+    NON_ALPHANUMERIC,
+};
+
+const ITEM: &str = "item";
+const COMMENT: &str = "comment";
+const USER: &str = "user";
+
+/// the characters encoded in a segment of the path,
+/// which are all but the alphanumerics and the unreserved `_`, `-`, `.` and `~`
+const PATH_SEGMENT: &AsciiSet = &NON_ALPHANUMERIC
+    .remove(b'_')
+    .remove(b'-')
+    .remove(b'.')
+    .remove(b'~');
+
+/// the path the current version of the json api of the server is mounted at
+pub const API_V1: &str = "/api/v1";
+
+/// A page in the app
+#[derive(Clone, Debug, PartialEq)]
+pub enum Route {
+    /// this page of the story listing
+    Stories { sorting: StorySorting, page: usize },
+    /// the story with its comment tree fetched within the limits of `options`
+    Item {
+        id: i64,
+        options: CommentFetchOptions,
+    },
+    /// the comment permalink
+    Comment(i64),
+    /// the user page of this username
+    User(String),
+}
+
+impl Default for Route {
+    fn default() -> Self {
+        Route::Stories {
+            sorting: StorySorting::default(),
+            page: 1,
+        }
+    }
+}
+
+impl Route {
+    /// parse the path and query of a url into a Route.
+    /// Trailing slashes and the fragment are ignored,
+    /// invalid or unknown query values are set to their default.
+    pub fn parse(url: &str) -> Option<Self> {
+        let url = url.split('#').next().unwrap_or(url);
+        let (path, query) = match url.split_once('?') {
+            Some((path, query)) => (path, query),
+            None => (url, ""),
+        };
+        let query = parse_query(query);
+        let query_value = |key: &str| -> Option<usize> {
+            query
+                .iter()
+                .find(|(k, _)| k == key)
+                .and_then(|(_, v)| v.parse::<usize>().ok())
+        };
+        let segments = path
+            .split('/')
+            .filter(|segment| !segment.is_empty())
+            .collect::<Vec<_>>();
+
+        match segments.as_slice() {
+            [] => Some(Route::Stories {
+                sorting: StorySorting::default(),
+                page: query_value("p").unwrap_or(1).max(1),
+            }),
+            [sorting] => {
+                let sorting = StorySorting::all()
+                    .into_iter()
+                    .find(|s| s.to_str() == *sorting)?;
+                Some(Route::Stories {
+                    sorting,
+                    page: query_value("p").unwrap_or(1).max(1),
+                })
+            }
+            [ITEM, id] => {
+                let default = CommentFetchOptions::default();
+                Some(Route::Item {
+                    id: id.parse().ok()?,
+                    options: CommentFetchOptions {
+                        max_depth: query_value("depth")
+                            .unwrap_or(default.max_depth),
+                        max_children_per_level: query_value("children")
+                            .unwrap_or(default.max_children_per_level),
+                        max_total: query_value("total")
+                            .unwrap_or(default.max_total),
+                    },
+                })
+            }
+            [COMMENT, id] => Some(Route::Comment(id.parse().ok()?)),
+            [USER, username] => {
+                let username = percent_decode_str(username)
+                    .decode_utf8()
+                    .ok()?
+                    .to_string();
+                Some(Route::User(username))
+            }
+            _ => None,
+        }
+    }
+
+    /// the url of this route, which parses back into the same route
+    pub fn to_url(&self) -> String {
+        match self {
+            Route::Stories { sorting, page } => sorting.to_page_url(*page),
+            Route::Item { id, options } => {
+                let default = CommentFetchOptions::default();
+                let mut query = vec![];
+                if options.max_depth != default.max_depth {
+                    query.push(format!("depth={}", options.max_depth));
+                }
+                if options.max_children_per_level
+                    != default.max_children_per_level
+                {
+                    query.push(format!(
+                        "children={}",
+                        options.max_children_per_level
+                    ));
+                }
+                if options.max_total != default.max_total {
+                    query.push(format!("total={}", options.max_total));
+                }
+                if query.is_empty() {
+                    format!("/{}/{}", ITEM, id)
+                } else {
+                    format!("/{}/{}?{}", ITEM, id, query.join("&"))
+                }
+            }
+            Route::Comment(id) => format!("/{}/{}", COMMENT, id),
+            Route::User(username) => format!(
+                "/{}/{}",
+                USER,
+                utf8_percent_encode(username, PATH_SEGMENT)
+            ),
+        }
+    }
+}
+
+/// the percent decoded key and value pairs of the query
+fn parse_query(query: &str) -> Vec<(String, String)> {
+    query
+        .split('&')
+        .filter(|pair| !pair.is_empty())
+        .map(|pair| {
+            let (key, value) = pair.split_once('=').unwrap_or((pair, ""));
+            (decode(key), decode(value))
+        })
+        .collect()
+}
+
+fn decode(s: &str) -> String {
+    percent_decode_str(&s.replace('+', " "))
+        .decode_utf8_lossy()
+        .to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+
+    #[test]
+    fn parse_paths() {
+        assert_eq!(Route::parse("/"), Some(Route::default()));
+        assert_eq!(Route::parse(""), Some(Route::default()));
+        assert_eq!(
+            Route::parse("/best/?p=3#top"),
+            Some(Route::Stories {
+                sorting: StorySorting::Best,
+                page: 3,
+            })
+        );
+        assert_eq!(
+            Route::parse("/new?p=abc"),
+            Some(Route::Stories {
+                sorting: StorySorting::New,
+                page: 1,
+            })
+        );
+        assert_eq!(
+            Route::parse("/item/123?depth=5"),
+            Some(Route::Item {
+                id: 123,
+                options: CommentFetchOptions {
+                    max_depth: 5,
+                    ..Default::default()
+                },
+            })
+        );
+        assert_eq!(Route::parse("/comment/42/"), Some(Route::Comment(42)));
+        assert_eq!(
+            Route::parse("/user/j%C3%B6rg"),
+            Some(Route::User("jörg".to_string()))
+        );
+    }
+
+    #[test]
+    fn encode_usernames() {
+        assert_eq!(
+            Route::User("foo_bar-1.x~".to_string()).to_url(),
+            "/user/foo_bar-1.x~"
+        );
+        assert_eq!(
+            Route::User("jörg/a b".to_string()).to_url(),
+            "/user/j%C3%B6rg%2Fa%20b"
+        );
+    }
+
+    #[test]
+    fn reject_invalid_paths() {
+        assert_eq!(Route::parse("/item"), None);
+        assert_eq!(Route::parse("/item/abc"), None);
+        assert_eq!(Route::parse("/comment/1/2"), None);
+        assert_eq!(Route::parse("/user/"), None);
+        assert_eq!(Route::parse("/unknown"), None);
+        assert_eq!(Route::parse("/user/%FF"), None);
+    }
+
+    fn any_sorting() -> impl Strategy<Value = StorySorting> {
+        prop::sample::select(StorySorting::all())
+    }
+
+    fn any_route() -> impl Strategy<Value = Route> {
+        prop_oneof![
+            (any_sorting(), 1..1000usize)
+                .prop_map(|(sorting, page)| Route::Stories { sorting, page }),
+            (any::<i64>(), 0..20usize, 0..100usize, 0..2000usize).prop_map(
+                |(id, max_depth, max_children_per_level, max_total)| {
+                    Route::Item {
+                        id,
+                        options: CommentFetchOptions {
+                            max_depth,
+                            max_children_per_level,
+                            max_total,
+                        },
+                    }
+                }
+            ),
+            any::<i64>().prop_map(Route::Comment),
+            ".+".prop_map(Route::User),
+        ]
+    }
+
+    proptest! {
+        #[test]
+        fn url_round_trip(route in any_route()) {
+            prop_assert_eq!(Route::parse(&route.to_url()), Some(route));
+        }
+
+        #[test]
+        fn parse_never_panics(url in ".*") {
+            let _ = Route::parse(&url);
+        }
+    }
+}
@@ -0,0 +1,214 @@
+//! A static snapshot of the hacker news data, which is read instead of the api
+//! where there is no server, such as on static hosting sites.
+//!
+//! A snapshot is a single json file with the story ids of each listing,
+//! and the items and users exactly as they are returned by the api.
+use crate::{
+    api::{
+        HnClient,
+        ServerError,
+        STORIES_COUNT,
+    },
+    types::{
+        CommentFetchOptions,
+        StorySorting,
+    },
+};
+use chrono::{
+    DateTime,
+    Utc,
+};
+use futures::future::join_all;
+use serde::{
+    de::DeserializeOwned,
+    Deserialize,
+    Serialize,
+};
+use serde_json::Value;
+use std::collections::{
+    BTreeMap,
+    BTreeSet,
+};
+use thiserror::Error;
+
+/// the version of the snapshot format, incremented on incompatible changes
+pub const SNAPSHOT_VERSION: u32 = 1;
+
+/// the file name of the snapshot, which the static client loads next to its page
+pub const SNAPSHOT_FILE: &str = "snapshot.json";
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Snapshot {
+    pub version: u32,
+    pub created_at: DateTime<Utc>,
+    /// the story ids of each listing, keyed by the `StorySorting::to_str`
+    pub story_ids: BTreeMap<String, Vec<i64>>,
+    /// the items as returned by the api, keyed by their id
+    pub items: BTreeMap<i64, Value>,
+    /// the users as returned by the api, keyed by their username
+    pub users: BTreeMap<String, Value>,
+}
+
+#[derive(Debug, Error)]
+pub enum SnapshotError {
+    #[error("unsupported snapshot version {0}, expecting version {}", SNAPSHOT_VERSION)]
+    UnsupportedVersion(u32),
+    #[error("Error decoding the snapshot: {0}")]
+    Decode(#[from] serde_json::Error),
+    #[error("Error fetching the snapshot: {0}")]
+    Fetch(#[from] ServerError),
+}
+
+impl Default for Snapshot {
+    fn default() -> Self {
+        Self {
+            version: SNAPSHOT_VERSION,
+            created_at: Utc::now(),
+            story_ids: BTreeMap::new(),
+            items: BTreeMap::new(),
+            users: BTreeMap::new(),
+        }
+    }
+}
+
+impl Snapshot {
+    /// take a snapshot of the first `pages` of every listing, along with the comments
+    /// within the limits of `options` and the users who submitted the stories
+    pub async fn create(
+        client: &HnClient,
+        pages: usize,
+        options: &CommentFetchOptions,
+    ) -> Result<Self, ServerError> {
+        let mut snapshot = Snapshot::default();
+        let mut stories = BTreeSet::new();
+        for sort in StorySorting::all() {
+            let mut story_ids = client.fetch_story_ids(sort).await?;
+            story_ids.truncate(pages.saturating_mul(STORIES_COUNT));
+            stories.extend(story_ids.iter().copied());
+            snapshot
+                .story_ids
+                .insert(sort.to_str().to_string(), story_ids);
+        }
+
+        // The items are fetched level by level, counting the depth and the total of the comments
+        // of each story the same way `HnClient::get_story_with_options` does,
+        // so the stories read from the snapshot have the same comments as the ones from the api.
+        let mut users = BTreeSet::new();
+        // the comments which can still be fetched for each story
+        let mut budgets: BTreeMap<i64, usize> = BTreeMap::new();
+        let mut pending: Vec<(i64, Pending)> =
+            stories.iter().map(|id| (*id, Pending::Story)).collect();
+        while !pending.is_empty() {
+            let fetched = join_all(pending.iter().map(|(item_id, kind)| async move {
+                (*item_id, *kind, client.fetch_item::<Value>(*item_id).await)
+            }))
+            .await;
+            pending = vec![];
+            for (item_id, kind, item) in fetched {
+                // the items which failed to load are left out of the snapshot
+                let item = match item {
+                    Ok(item) => item,
+                    Err(_) => continue,
+                };
+                let replies = match kind {
+                    Pending::Story => {
+                        if let Some(by) = item["by"].as_str() {
+                            users.insert(by.to_string());
+                        }
+                        pending.extend(
+                            item_ids(&item["parts"])
+                                .map(|part| (part, Pending::PollOption)),
+                        );
+                        Some((item_id, options.max_depth))
+                    }
+                    Pending::Comment { story_id, depth } if depth > 0 => {
+                        Some((story_id, depth - 1))
+                    }
+                    Pending::Comment { .. } | Pending::PollOption => None,
+                };
+                if let Some((story_id, depth)) = replies {
+                    let budget =
+                        budgets.entry(story_id).or_insert(options.max_total);
+                    let count = item_ids(&item["kids"])
+                        .count()
+                        .min(options.max_children_per_level)
+                        .min(*budget);
+                    *budget -= count;
+                    pending.extend(
+                        item_ids(&item["kids"])
+                            .take(count)
+                            .map(|kid| (kid, Pending::Comment { story_id, depth })),
+                    );
+                }
+                snapshot.items.insert(item_id, item);
+            }
+            pending.retain(|(item_id, _)| !snapshot.items.contains_key(item_id));
+        }
+
+        let fetched = join_all(users.iter().map(|user_id| async move {
+            (user_id, client.fetch_user::<Value>(user_id).await)
+        }))
+        .await;
+        for (user_id, user) in fetched {
+            if let Ok(user) = user {
+                snapshot.users.insert(user_id.to_string(), user);
+            }
+        }
+        Ok(snapshot)
+    }
+
+    /// read the snapshot from its json, checking that it is of the supported version
+    pub fn from_json(json: &str) -> Result<Self, SnapshotError> {
+        Self::from_value(serde_json::from_str(json)?)
+    }
+
+    fn from_value(value: Value) -> Result<Self, SnapshotError> {
+        let version = value["version"].as_u64().unwrap_or_default() as u32;
+        if version != SNAPSHOT_VERSION {
+            return Err(SnapshotError::UnsupportedVersion(version));
+        }
+        Ok(serde_json::from_value(value)?)
+    }
+
+    /// fetch the snapshot at this url
+    pub async fn fetch(url: &str) -> Result<Self, SnapshotError> {
+        let value = HnClient::default()
+            .make_json_get_request::<Value>(url)
+            .await?;
+        Self::from_value(value)
+    }
+
+    pub fn story_ids(&self, sort: StorySorting) -> Result<Vec<i64>, ServerError> {
+        self.story_ids
+            .get(sort.to_str())
+            .cloned()
+            .ok_or(ServerError::NotFound)
+    }
+
+    pub fn item<T: DeserializeOwned>(&self, item_id: i64) -> Result<T, ServerError> {
+        let item = self.items.get(&item_id).ok_or(ServerError::NotFound)?;
+        Ok(T::deserialize(item)?)
+    }
+
+    pub fn user<T: DeserializeOwned>(&self, user_id: &str) -> Result<T, ServerError> {
+        let user = self.users.get(user_id).ok_or(ServerError::NotFound)?;
+        Ok(T::deserialize(user)?)
+    }
+}
+
+/// What an item which is yet to be fetched into the snapshot is
+#[derive(Copy, Clone, Debug)]
+enum Pending {
+    Story,
+    /// a comment of this story, along with the levels of its replies which are fetched
+    Comment { story_id: i64, depth: usize },
+    PollOption,
+}
+
+/// the ids in this json array of ids, such as the `kids` and `parts` of an item
+fn item_ids(ids: &Value) -> impl Iterator<Item = i64> + '_ {
+    ids.as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(|id| id.as_i64())
+}
@@ -1,8 +1,13 @@
+use crate::route::Route;
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
+use std::cmp::Reverse;
 
-#[derive(Copy, Clone, PartialEq)]
+#[derive(
+    Copy, Clone, Debug, PartialEq, Eq, Hash, Default, Deserialize, Serialize,
+)]
 pub enum StorySorting {
+    #[default]
     Top,
     New,
     Best,
@@ -30,10 +35,6 @@ impl StorySorting {
             StorySorting::Job,
         ]
     }
-    /// match url to StorySorting
-    pub fn from_url(url: &str) -> Option<Self> {
-        Self::all().into_iter().find(|s| url == s.to_url())
-    }
     /// return the str for assembling paths in warp
     pub fn to_str(&self) -> &str {
         match self {
@@ -49,17 +50,212 @@ impl StorySorting {
     pub fn to_url(&self) -> String {
         format!("/{}", self.to_str())
     }
+
+    /// the url of this page of the story listing
+    pub fn to_page_url(&self, page: usize) -> String {
+        if page > 1 {
+            format!("{}?p={}", self.to_url(), page)
+        } else {
+            self.to_url()
+        }
+    }
 }
 
-impl Default for StorySorting {
-    fn default() -> Self {
-        StorySorting::Top
+/// The order of the stories in a page of a story listing
+#[derive(Copy, Clone, Debug, PartialEq, Default, Deserialize, Serialize)]
+#[cfg_attr(feature = "openapi", derive(schemars::JsonSchema))]
+#[serde(rename_all = "lowercase")]
+pub enum StoryOrder {
+    /// the ranking of the listing as returned by hacker news
+    #[default]
+    Rank,
+    /// highest score first
+    Score,
+    /// newest first
+    Time,
+    /// most comments first
+    Comments,
+}
+
+impl StoryOrder {
+    /// sort the stories which are in ranking order
+    pub fn sort(&self, stories: &mut [StoryItem]) {
+        match self {
+            StoryOrder::Rank => (),
+            StoryOrder::Score => stories.sort_by_key(|s| Reverse(s.score)),
+            StoryOrder::Time => stories.sort_by_key(|s| Reverse(s.time)),
+            StoryOrder::Comments => {
+                stories.sort_by_key(|s| Reverse(s.descendants))
+            }
+        }
     }
 }
 
+/// A page of stories from one of the story listing
 #[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub struct StoryList {
+    pub sorting: StorySorting,
+    /// the page number, starting at 1
+    pub page: usize,
+    /// the number of stories per page
+    pub per_page: usize,
+    /// the total number of stories in the listing
+    pub total: usize,
+    /// the order of the stories in this page
+    #[serde(default)]
+    pub order: StoryOrder,
+    pub stories: Vec<StoryItem>,
+    /// the stories in this page which failed to load
+    #[serde(default)]
+    pub failed: Vec<FailedItem>,
+}
+
+impl StoryList {
+    /// the position of the first story of this page in the listing
+    pub fn offset(&self) -> usize {
+        (self.page.max(1) - 1).saturating_mul(self.per_page)
+    }
+
+    /// the next page number, if there are more stories after this page
+    pub fn next_page(&self) -> Option<usize> {
+        if self.offset().saturating_add(self.per_page) < self.total {
+            Some(self.page + 1)
+        } else {
+            None
+        }
+    }
+}
+
+/// The type of an item in hacker news
+#[derive(Copy, Clone, Debug, PartialEq, Default, Deserialize, Serialize)]
+#[cfg_attr(feature = "openapi", derive(schemars::JsonSchema))]
+#[serde(rename_all = "lowercase")]
+pub enum ItemType {
+    Job,
+    #[default]
+    Story,
+    Comment,
+    Poll,
+    PollOpt,
+}
+
+impl ItemType {
+    /// whether the item is listed as a story, ie: a story, job or poll
+    pub fn is_story(&self) -> bool {
+        matches!(self, ItemType::Story | ItemType::Job | ItemType::Poll)
+    }
+}
+
+/// Any of the items in hacker news, distinguished by its `type`
+#[derive(Clone, Debug, PartialEq, Serialize)]
+#[serde(untagged)]
+pub enum Item {
+    Story(StoryItem),
+    Comment(Comment),
+    Job(StoryItem),
+    Poll(StoryItem),
+    PollOpt(PollOption),
+}
+
+impl<'de> Deserialize<'de> for Item {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        use serde::de::Error;
+
+        let value = serde_json::Value::deserialize(deserializer)?;
+        let item_type = value
+            .get("type")
+            .cloned()
+            .map(ItemType::deserialize)
+            .transpose()
+            .map_err(D::Error::custom)?
+            .unwrap_or_default();
+        let item = match item_type {
+            ItemType::Story => serde_json::from_value(value).map(Item::Story),
+            ItemType::Comment => {
+                serde_json::from_value(value).map(Item::Comment)
+            }
+            ItemType::Job => serde_json::from_value(value).map(Item::Job),
+            ItemType::Poll => serde_json::from_value(value).map(Item::Poll),
+            ItemType::PollOpt => {
+                serde_json::from_value(value).map(Item::PollOpt)
+            }
+        };
+        item.map_err(D::Error::custom)
+    }
+}
+
+impl Item {
+    pub fn id(&self) -> i64 {
+        match self {
+            Item::Story(story) | Item::Job(story) | Item::Poll(story) => {
+                story.id
+            }
+            Item::Comment(comment) => comment.id,
+            Item::PollOpt(poll_option) => poll_option.id,
+        }
+    }
+
+    pub fn item_type(&self) -> ItemType {
+        match self {
+            Item::Story(_) => ItemType::Story,
+            Item::Comment(_) => ItemType::Comment,
+            Item::Job(_) => ItemType::Job,
+            Item::Poll(_) => ItemType::Poll,
+            Item::PollOpt(_) => ItemType::PollOpt,
+        }
+    }
+
+    /// the story, job or poll, and `None` for the comments and poll options
+    pub fn into_story(self) -> Option<StoryItem> {
+        match self {
+            Item::Story(story) | Item::Job(story) | Item::Poll(story) => {
+                Some(story)
+            }
+            Item::Comment(_) | Item::PollOpt(_) => None,
+        }
+    }
+
+    /// the comment, and `None` for the other items
+    pub fn into_comment(self) -> Option<Comment> {
+        match self {
+            Item::Comment(comment) => Some(comment),
+            _ => None,
+        }
+    }
+}
+
+/// An option of a poll, with its votes as the score
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+#[cfg_attr(feature = "openapi", derive(schemars::JsonSchema))]
+pub struct PollOption {
+    pub id: i64,
+    /// the poll this option belongs to
+    pub poll: i64,
+    #[serde(default)]
+    pub by: String,
+    #[serde(default)]
+    pub text: String,
+    #[serde(default)]
+    pub score: i64,
+    #[serde(with = "chrono::serde::ts_seconds")]
+    #[cfg_attr(feature = "openapi", schemars(with = "i64"))]
+    pub time: DateTime<Utc>,
+    #[serde(default)]
+    pub deleted: bool,
+    #[serde(default)]
+    pub dead: bool,
+    pub r#type: ItemType,
+}
+
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+#[cfg_attr(feature = "openapi", derive(schemars::JsonSchema))]
 pub struct StoryPageData {
     pub id: i64,
+    /// there will be no title if the story was deleted
+    #[serde(default)]
     pub title: String,
     pub url: Option<String>,
     pub text: Option<String>,
@@ -70,15 +266,65 @@ pub struct StoryPageData {
     #[serde(default)]
     pub descendants: i64,
     #[serde(with = "chrono::serde::ts_seconds")]
+    #[cfg_attr(feature = "openapi", schemars(with = "i64"))]
     pub time: DateTime<Utc>,
     #[serde(default)]
     pub kids: Vec<i64>,
-    pub r#type: String,
+    /// the options of a poll
+    #[serde(default)]
+    pub parts: Vec<i64>,
+    #[serde(default)]
+    pub deleted: bool,
+    #[serde(default)]
+    pub dead: bool,
+    pub r#type: ItemType,
     #[serde(default)]
     pub comments: Vec<Comment>,
+    /// the fetched options of a poll, in the order of `parts`
+    #[serde(default)]
+    pub poll_options: Vec<PollOption>,
+    /// the number of top-level comments in `kids` that were not fetched into `comments`
+    #[serde(default)]
+    pub unfetched_kids: usize,
+    /// the top-level comments and poll options which failed to load
+    #[serde(default)]
+    pub failed: Vec<FailedItem>,
+    /// the top-level comments in `kids` which do not exist, so they are not fetched again
+    #[serde(default)]
+    pub not_found_kids: Vec<i64>,
 }
 
 impl StoryPageData {
+    /// the top-level comments in `kids` which are not yet fetched into `comments`
+    pub fn missing_kids(&self) -> Vec<i64> {
+        missing_kids(&self.kids, &self.comments, &self.not_found_kids)
+    }
+
+    /// add the fetched top-level comments, keeping the order of `kids`
+    pub fn add_comments(&mut self, comments: Fetched<Comment>) {
+        add_failed(&mut self.failed, &comments);
+        add_not_found(&mut self.not_found_kids, &comments);
+        add_kids(&self.kids, &mut self.comments, comments.items);
+        self.unfetched_kids = self.missing_kids().len();
+    }
+
+    /// the number of items which failed to load in this story and its comment tree
+    pub fn failed_count(&self) -> usize {
+        self.failed.len()
+            + self
+                .comments
+                .iter()
+                .map(|comment| comment.failed_count())
+                .sum::<usize>()
+    }
+
+    /// find the comment with this id in the comment tree of this story
+    pub fn find_comment_mut(&mut self, comment_id: i64) -> Option<&mut Comment> {
+        self.comments
+            .iter_mut()
+            .find_map(|comment| comment.find_mut(comment_id))
+    }
+
     /// derive a preview of this StoragePageData
     pub fn preview(&self) -> StoryItem {
         StoryItem {
@@ -91,12 +337,57 @@ impl StoryPageData {
             descendants: self.descendants,
             time: self.time.to_owned(),
             kids: self.kids.to_owned(),
-            r#type: self.r#type.to_owned(),
+            parts: self.parts.to_owned(),
+            deleted: self.deleted,
+            dead: self.dead,
+            r#type: self.r#type,
+        }
+    }
+}
+
+/// Limits on how much of a comment tree is fetched
+#[derive(Copy, Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub struct CommentFetchOptions {
+    /// the number of levels of replies fetched below a comment
+    pub max_depth: usize,
+    /// the number of replies fetched for each comment
+    pub max_children_per_level: usize,
+    /// the total number of comments fetched in the whole tree
+    pub max_total: usize,
+}
+
+impl Default for CommentFetchOptions {
+    fn default() -> Self {
+        Self {
+            max_depth: 3,
+            max_children_per_level: 3,
+            max_total: 200,
+        }
+    }
+}
+
+impl CommentFetchOptions {
+    /// the largest options a client is allowed to request
+    pub const MAX: Self = Self {
+        max_depth: 10,
+        max_children_per_level: 50,
+        max_total: 1000,
+    };
+
+    /// limit each of the options to at most the options in `max`
+    pub fn limit(&self, max: &Self) -> Self {
+        Self {
+            max_depth: self.max_depth.min(max.max_depth),
+            max_children_per_level: self
+                .max_children_per_level
+                .min(max.max_children_per_level),
+            max_total: self.max_total.min(max.max_total),
         }
     }
 }
 
 #[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+#[cfg_attr(feature = "openapi", derive(schemars::JsonSchema))]
 pub struct Comment {
     pub id: i64,
     /// there will be no by field if the comment was deleted
@@ -105,28 +396,72 @@ pub struct Comment {
     #[serde(default)]
     pub text: String,
     #[serde(with = "chrono::serde::ts_seconds")]
+    #[cfg_attr(feature = "openapi", schemars(with = "i64"))]
     pub time: DateTime<Utc>,
     #[serde(default)]
     pub kids: Vec<i64>,
+    /// the comment or story this comment is replying to
+    pub parent: Option<i64>,
+    #[serde(default)]
+    pub deleted: bool,
+    #[serde(default)]
+    pub dead: bool,
     #[serde(default)]
     pub sub_comments: Vec<Comment>,
-    pub r#type: String,
+    /// the number of replies in `kids` that were not fetched into `sub_comments`
+    #[serde(default)]
+    pub unfetched_kids: usize,
+    /// the replies which failed to load
+    #[serde(default)]
+    pub failed: Vec<FailedItem>,
+    /// the replies in `kids` which do not exist, so they are not fetched again
+    #[serde(default)]
+    pub not_found_kids: Vec<i64>,
+    pub r#type: ItemType,
 }
 
 impl Comment {
-    /// attempt to extract story id from url
-    pub fn id_from_url(url: &str) -> Option<i64> {
-        if url.starts_with("/comment") {
-            let splinters = url.split("/").collect::<Vec<_>>();
-            if splinters.len() >= 3 {
-                assert_eq!("", splinters[0]);
-                assert_eq!("comment", splinters[1]);
-                splinters[2].parse::<i64>().ok()
-            } else {
-                None
-            }
+    /// the replies in `kids` which are not yet fetched into `sub_comments`
+    pub fn missing_kids(&self) -> Vec<i64> {
+        missing_kids(&self.kids, &self.sub_comments, &self.not_found_kids)
+    }
+
+    /// add the fetched replies, keeping the order of `kids`
+    pub fn add_replies(&mut self, replies: Fetched<Comment>) {
+        add_failed(&mut self.failed, &replies);
+        add_not_found(&mut self.not_found_kids, &replies);
+        add_kids(&self.kids, &mut self.sub_comments, replies.items);
+        self.unfetched_kids = self.missing_kids().len();
+    }
+
+    /// the number of replies which failed to load below this comment
+    pub fn failed_count(&self) -> usize {
+        self.failed.len()
+            + self
+                .sub_comments
+                .iter()
+                .map(|sub| sub.failed_count())
+                .sum::<usize>()
+    }
+
+    /// the number of replies below this comment, including the ones not yet fetched
+    pub fn descendant_count(&self) -> usize {
+        self.missing_kids().len()
+            + self
+                .sub_comments
+                .iter()
+                .map(|sub| 1 + sub.descendant_count())
+                .sum::<usize>()
+    }
+
+    /// find the comment with this id in this comment or its replies
+    pub fn find_mut(&mut self, comment_id: i64) -> Option<&mut Comment> {
+        if self.id == comment_id {
+            Some(self)
         } else {
-            None
+            self.sub_comments
+                .iter_mut()
+                .find_map(|sub| sub.find_mut(comment_id))
         }
     }
 
@@ -135,9 +470,55 @@ impl Comment {
     }
 }
 
+/// the ids in `kids` which are neither in the fetched `comments` nor `not_found`
+fn missing_kids(
+    kids: &[i64],
+    comments: &[Comment],
+    not_found: &[i64],
+) -> Vec<i64> {
+    kids.iter()
+        .filter(|kid| !comments.iter().any(|c| c.id == **kid))
+        .filter(|kid| !not_found.contains(kid))
+        .copied()
+        .collect()
+}
+
+/// add the ids of the `fetched` comments which do not exist, which are not yet in `not_found`
+fn add_not_found(not_found: &mut Vec<i64>, fetched: &Fetched<Comment>) {
+    for id in &fetched.not_found {
+        if !not_found.contains(id) {
+            not_found.push(*id);
+        }
+    }
+}
+
+/// replace the failures of the comments which were fetched again with their new failures
+fn add_failed(failed: &mut Vec<FailedItem>, fetched: &Fetched<Comment>) {
+    failed.retain(|item| {
+        !fetched.items.iter().any(|c| c.id == item.id)
+            && !fetched.failed.iter().any(|f| f.id == item.id)
+    });
+    failed.extend(fetched.failed.iter().cloned());
+}
+
+/// add the `fetched` comments which are not yet in `comments`, sorted in the order of `kids`
+fn add_kids(kids: &[i64], comments: &mut Vec<Comment>, fetched: Vec<Comment>) {
+    for comment in fetched {
+        if !comments.iter().any(|c| c.id == comment.id) {
+            comments.push(comment);
+        }
+    }
+    comments.sort_by_key(|c| {
+        kids.iter().position(|kid| *kid == c.id).unwrap_or(kids.len())
+    });
+}
+
 #[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+#[cfg_attr(feature = "openapi", derive(schemars::JsonSchema))]
 pub struct StoryItem {
     pub id: i64,
+    /// there will be no title if the story was deleted
+    #[serde(default)]
     pub title: String,
     pub url: Option<String>,
     pub text: Option<String>,
@@ -148,35 +529,29 @@ pub struct StoryItem {
     #[serde(default)]
     pub descendants: i64,
     #[serde(with = "chrono::serde::ts_seconds")]
+    #[cfg_attr(feature = "openapi", schemars(with = "i64"))]
     pub time: DateTime<Utc>,
     #[serde(default)]
     pub kids: Vec<i64>,
-    pub r#type: String,
+    /// the options of a poll
+    #[serde(default)]
+    pub parts: Vec<i64>,
+    #[serde(default)]
+    pub deleted: bool,
+    #[serde(default)]
+    pub dead: bool,
+    /// a story, job or poll
+    pub r#type: ItemType,
 }
 
 impl StoryItem {
-    /// attempt to extract story id from url
-    pub fn id_from_url(url: &str) -> Option<i64> {
-        if url.starts_with("/item") {
-            let splinters = url.split("/").collect::<Vec<_>>();
-            if splinters.len() >= 3 {
-                assert_eq!("", splinters[0]);
-                assert_eq!("item", splinters[1]);
-                splinters[2].parse::<i64>().ok()
-            } else {
-                None
-            }
-        } else {
-            None
-        }
-    }
-
     pub fn to_url(story_id: i64) -> String {
         format!("/item/{}", story_id)
     }
 }
 
 #[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+#[cfg_attr(feature = "openapi", derive(schemars::JsonSchema))]
 pub struct UserData {
     pub id: String,
     pub karma: i64,
@@ -186,26 +561,114 @@ pub struct UserData {
     pub submitted: Vec<i64>,
     #[serde(default)]
     pub stories: Vec<StoryItem>,
+    /// the submitted stories which failed to load
+    #[serde(default)]
+    pub failed: Vec<FailedItem>,
 }
 
 impl UserData {
-    /// attempt to extract story id from url
-    pub fn id_from_url(url: &str) -> Option<String> {
-        if url.starts_with("/user") {
-            let splinters = url.split("/").collect::<Vec<_>>();
-            if splinters.len() >= 3 {
-                assert_eq!("", splinters[0]);
-                assert_eq!("user", splinters[1]);
-                Some(splinters[2].to_string())
-            } else {
-                None
-            }
-        } else {
-            None
+    pub fn to_url(username: &str) -> String {
+        Route::User(username.to_string()).to_url()
+    }
+}
+
+/// An item which failed to load, ie: when the api timed out.
+/// Only the kind of the error is exposed, its message may have the urls of the upstream api.
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+#[cfg_attr(feature = "openapi", derive(schemars::JsonSchema))]
+pub struct FailedItem {
+    pub id: i64,
+    /// a stable code of the kind of the error, ie: `timeout`
+    pub code: String,
+}
+
+/// The items which were fetched, along with the ones which failed to load
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub struct Fetched<T> {
+    pub items: Vec<T>,
+    pub failed: Vec<FailedItem>,
+    /// the ids of the items which do not exist, ie: a `null` kid
+    #[serde(default)]
+    pub not_found: Vec<i64>,
+}
+
+impl<T> Default for Fetched<T> {
+    fn default() -> Self {
+        Self {
+            items: vec![],
+            failed: vec![],
+            not_found: vec![],
         }
     }
+}
 
-    pub fn to_url(username: &str) -> String {
-        format!("/user/{}", username)
+/// The response of every route of the json api,
+/// which has either the `data` or the `error`
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+#[cfg_attr(feature = "openapi", derive(schemars::JsonSchema))]
+pub struct ApiResponse<T> {
+    pub data: Option<T>,
+    /// the paging of the story listings
+    pub pagination: Option<Pagination>,
+    /// the time this response was made
+    pub fetched_at: DateTime<Utc>,
+    pub error: Option<ApiError>,
+}
+
+/// The paging of a story listing
+#[derive(Copy, Clone, Debug, PartialEq, Deserialize, Serialize)]
+#[cfg_attr(feature = "openapi", derive(schemars::JsonSchema))]
+pub struct Pagination {
+    /// the page number, starting at 1
+    pub page: usize,
+    pub per_page: usize,
+    /// the total number of stories in the listing
+    pub total: usize,
+    pub next_page: Option<usize>,
+}
+
+/// The error of the json api
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+#[cfg_attr(feature = "openapi", derive(schemars::JsonSchema))]
+pub struct ApiError {
+    /// a stable code of the kind of the error, ie: `not_found`, `timeout`
+    pub code: String,
+    /// the human readable description of the error
+    pub message: String,
+}
+
+impl<T> ApiResponse<T> {
+    pub fn ok(data: T) -> Self {
+        Self {
+            data: Some(data),
+            pagination: None,
+            fetched_at: Utc::now(),
+            error: None,
+        }
+    }
+
+    pub fn error(error: ApiError) -> Self {
+        Self {
+            data: None,
+            pagination: None,
+            fetched_at: Utc::now(),
+            error: Some(error),
+        }
+    }
+
+    pub fn with_pagination(mut self, pagination: Pagination) -> Self {
+        self.pagination = Some(pagination);
+        self
+    }
+}
+
+impl From<&StoryList> for Pagination {
+    fn from(story_list: &StoryList) -> Self {
+        Self {
+            page: story_list.page,
+            per_page: story_list.per_page,
+            total: story_list.total,
+            next_page: story_list.next_page(),
+        }
     }
 }
@@ -0,0 +1,153 @@
+#![deny(warnings)]
+//! The api client against the recorded fixtures served by `mock-hn`, with no network
+use common::{
+    api::{HnClient, ServerError},
+    types::{CommentFetchOptions, ItemType, StoryOrder, StorySorting},
+};
+use mock_hn::MockHn;
+
+lazy_static::lazy_static! {
+    static ref MOCK_HN: MockHn = MockHn::start();
+}
+
+/// a client of its own for each test, so the tests do not share a cache
+fn client() -> HnClient {
+    HnClient::new(MOCK_HN.base_url()).expect("must build the client")
+}
+
+#[tokio::test]
+async fn stories_are_in_the_ranking_order() {
+    let story_list = client()
+        .get_stories_with_sorting(StorySorting::Top, 1, StoryOrder::Rank)
+        .await
+        .expect("must get the stories");
+    let ids: Vec<i64> = story_list.stories.iter().map(|s| s.id).collect();
+    assert_eq!(ids, vec![8863, 121003, 192327]);
+    assert_eq!(story_list.total, 3);
+    assert_eq!(story_list.next_page(), None);
+}
+
+#[tokio::test]
+async fn stories_are_sorted_in_the_order() {
+    let story_list = client()
+        .get_stories_with_sorting(StorySorting::New, 1, StoryOrder::Score)
+        .await
+        .expect("must get the stories");
+    let scores: Vec<i64> = story_list.stories.iter().map(|s| s.score).collect();
+    assert_eq!(scores, vec![111, 25, 6]);
+}
+
+#[tokio::test]
+async fn a_page_past_the_listing_is_empty() {
+    let story_list = client()
+        .get_stories_with_sorting(StorySorting::Ask, 2, StoryOrder::Rank)
+        .await
+        .expect("must get the stories");
+    assert!(story_list.stories.is_empty());
+    assert_eq!(story_list.total, 1);
+
+    // the position of the page is past any listing, rather than overflowing
+    let story_list = client()
+        .get_stories_with_sorting(StorySorting::Top, usize::MAX, StoryOrder::Rank)
+        .await
+        .expect("must get the stories");
+    assert!(story_list.stories.is_empty());
+    assert_eq!(story_list.offset(), usize::MAX);
+    assert_eq!(story_list.next_page(), None);
+}
+
+#[tokio::test]
+async fn story_has_its_comment_tree() {
+    let story = client().get_story(8863).await.expect("must get the story");
+    assert_eq!(story.by, "dhouston");
+    let comments: Vec<i64> = story.comments.iter().map(|c| c.id).collect();
+    assert_eq!(comments, vec![9224, 8917]);
+    assert_eq!(story.comments[0].sub_comments[0].id, 9272);
+    assert_eq!(story.comments[1].sub_comments[0].id, 8952);
+    assert_eq!(story.unfetched_kids, 0);
+}
+
+#[tokio::test]
+async fn comment_budget_is_spent_breadth_first() {
+    let options = CommentFetchOptions {
+        max_total: 3,
+        ..Default::default()
+    };
+    let story = client()
+        .get_story_with_options(8863, &options)
+        .await
+        .expect("must get the story");
+    let comments: Vec<i64> = story.comments.iter().map(|c| c.id).collect();
+    assert_eq!(comments, vec![9224, 8917]);
+    // the last comment slot goes to the reply of the first comment
+    assert_eq!(story.comments[0].sub_comments[0].id, 9272);
+    assert!(story.comments[1].sub_comments.is_empty());
+    assert_eq!(story.comments[1].unfetched_kids, 1);
+}
+
+#[tokio::test]
+async fn missing_comments_are_not_failed() {
+    // the second comment of the story is `null`
+    let story = client().get_story(121003).await.expect("must get the story");
+    let comments: Vec<i64> = story.comments.iter().map(|c| c.id).collect();
+    assert_eq!(comments, vec![121016]);
+    assert!(story.failed.is_empty());
+    assert_eq!(story.not_found_kids, vec![121017]);
+    assert!(story.missing_kids().is_empty());
+    assert_eq!(story.unfetched_kids, 0);
+}
+
+#[tokio::test]
+async fn unknown_story_is_not_found() {
+    let result = client().get_story(1).await;
+    assert!(matches!(result, Err(ServerError::NotFound)));
+}
+
+#[tokio::test]
+async fn comment_is_fetched_down_to_the_depth() {
+    let client = client();
+    let comment = client
+        .get_comment_with_depth(9224, 0)
+        .await
+        .expect("must get the comment");
+    assert!(comment.sub_comments.is_empty());
+    assert_eq!(comment.unfetched_kids, 1);
+
+    let comment = client
+        .get_comment_with_depth(9224, 1)
+        .await
+        .expect("must get the comment");
+    assert_eq!(comment.sub_comments.len(), 1);
+    assert_eq!(comment.sub_comments[0].by, "dhouston");
+    assert_eq!(comment.unfetched_kids, 0);
+}
+
+#[tokio::test]
+async fn user_page_lists_only_the_stories() {
+    let user = client()
+        .get_user_page("dhouston")
+        .await
+        .expect("must get the user");
+    assert_eq!(user.karma, 4076);
+    assert_eq!(user.submitted, vec![9272, 8952, 8863]);
+    let stories: Vec<i64> = user.stories.iter().map(|s| s.id).collect();
+    assert_eq!(stories, vec![8863]);
+}
+
+#[tokio::test]
+async fn unknown_user_is_not_found() {
+    let result = client().get_user_page("nobody").await;
+    assert!(matches!(result, Err(ServerError::NotFound)));
+}
+
+#[tokio::test]
+async fn items_of_another_type_are_not_found() {
+    let client = client();
+    // a comment has no story page, and a story is not a comment
+    let story = client.get_story(9224).await;
+    assert!(matches!(story, Err(ServerError::NotFound)));
+    let comment = client.get_comment(8863).await;
+    assert!(matches!(comment, Err(ServerError::NotFound)));
+    let item = client.get_item(9224).await.expect("must get the item");
+    assert_eq!(item.item_type(), ItemType::Comment);
+}
@@ -0,0 +1,82 @@
+#![deny(warnings)]
+#![cfg(feature = "caching")]
+//! The cached responses of the `mock-hn` api are kept apart by api, and bounded on disk
+use common::{
+    api::{HnClient, MemoryCacheStore},
+    types::CommentFetchOptions,
+};
+use mock_hn::MockHn;
+
+#[tokio::test]
+async fn cache_is_not_shared_between_apis() {
+    let mock_hn = MockHn::start();
+    let mirror = MockHn::start();
+    let builder = HnClient::builder()
+        .base_url(mock_hn.base_url())
+        .cache_store(MemoryCacheStore::default());
+    // the clients of the cloned builder share the cache store
+    let client = builder.clone().build().expect("must build the client");
+    let mirror_client = builder
+        .base_url(mirror.base_url())
+        .build()
+        .expect("must build the client");
+
+    client.get_story_preview(8863).await.expect("must get the story");
+    client.get_story_preview(8863).await.expect("must get the story");
+    assert_eq!(mock_hn.requests(), 1);
+
+    mirror_client
+        .get_story_preview(8863)
+        .await
+        .expect("must get the story");
+    assert_eq!(mirror.requests(), 1);
+}
+
+#[tokio::test]
+async fn largest_comment_tree_is_cached() {
+    let mock_hn = MockHn::start();
+    let client =
+        HnClient::new(mock_hn.base_url()).expect("must build the client");
+    let options = CommentFetchOptions::MAX;
+    client
+        .get_story_with_options(8863, &options)
+        .await
+        .expect("must get the story");
+    let requests = mock_hn.requests();
+    client
+        .get_story_with_options(8863, &options)
+        .await
+        .expect("must get the story");
+    assert_eq!(mock_hn.requests(), requests);
+}
+
+#[cfg(feature = "disk-cache")]
+#[test]
+fn disk_cache_is_swept() {
+    use chrono::{Duration, Utc};
+    use common::api::{CacheEntry, CacheStore, DiskCacheStore};
+
+    let dir = std::env::temp_dir()
+        .join(format!("hn-disk-cache-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    let store = DiskCacheStore::open(&dir)
+        .expect("must open the cache")
+        .capacity(2)
+        .max_age(std::time::Duration::from_secs(60 * 60));
+    let entry = |age: Duration| CacheEntry {
+        value: "null".to_string(),
+        fetched_at: Utc::now() - age,
+    };
+    store.put("expired", entry(Duration::hours(2)));
+    store.put("oldest", entry(Duration::minutes(3)));
+    store.put("older", entry(Duration::minutes(2)));
+    store.put("newest", entry(Duration::minutes(1)));
+    store.sweep();
+
+    assert_eq!(store.len(), 2);
+    assert!(store.get("expired").is_none());
+    assert!(store.get("oldest").is_none());
+    assert!(store.get("older").is_some());
+    assert!(store.get("newest").is_some());
+    let _ = std::fs::remove_dir_all(&dir);
+}
@@ -0,0 +1,97 @@
+#![deny(warnings)]
+//! The responses recorded from the `mock-hn` api are replayed with no api at all
+use common::api::{Cassette, HnClient, ServerError};
+use mock_hn::MockHn;
+use std::path::PathBuf;
+
+/// an empty cassette directory of its own for each test
+fn cassette_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir()
+        .join(format!("hn-cassette-{}-{}", name, std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    dir
+}
+
+/// a client to an api which is not there, to be sure the responses are replayed
+fn replay_client(dir: &PathBuf) -> HnClient {
+    HnClient::builder()
+        .base_url("http://127.0.0.1:9/v0")
+        .cassette(Cassette::replay(dir))
+        .build()
+        .expect("must build the client")
+}
+
+#[tokio::test]
+async fn recorded_story_is_replayed() {
+    let mock_hn = MockHn::start();
+    let dir = cassette_dir("story");
+    let recorder = HnClient::builder()
+        .base_url(mock_hn.base_url())
+        .cassette(Cassette::record(&dir))
+        .build()
+        .expect("must build the client");
+    let recorded = recorder.get_story(8863).await.expect("must get the story");
+    assert!(dir.join("item_8863.json").exists());
+
+    let replayed = replay_client(&dir)
+        .get_story(8863)
+        .await
+        .expect("must replay the story");
+    assert_eq!(replayed, recorded);
+    std::fs::remove_dir_all(&dir).expect("must remove the cassette");
+}
+
+#[tokio::test]
+async fn recorded_user_page_is_replayed() {
+    let mock_hn = MockHn::start();
+    let dir = cassette_dir("user");
+    let recorder = HnClient::builder()
+        .base_url(mock_hn.base_url())
+        .cassette(Cassette::record(&dir))
+        .build()
+        .expect("must build the client");
+    let recorded = recorder
+        .get_user_page("dhouston")
+        .await
+        .expect("must get the user");
+
+    let replayed = replay_client(&dir)
+        .get_user_page("dhouston")
+        .await
+        .expect("must replay the user");
+    assert_eq!(replayed, recorded);
+
+    // the unknown user is recorded as the `null` the api responded with
+    assert!(matches!(
+        recorder.get_user_page("nobody").await,
+        Err(ServerError::NotFound)
+    ));
+    assert!(matches!(
+        replay_client(&dir).get_user_page("nobody").await,
+        Err(ServerError::NotFound)
+    ));
+    std::fs::remove_dir_all(&dir).expect("must remove the cassette");
+}
+
+#[tokio::test]
+async fn unrecorded_request_is_an_error() {
+    let dir = cassette_dir("empty");
+    let result = replay_client(&dir).get_story(8863).await;
+    assert!(matches!(result, Err(ServerError::NotRecorded(_))));
+}
+
+#[tokio::test]
+async fn response_which_can_not_be_recorded_is_an_error() {
+    let mock_hn = MockHn::start();
+    // the cassette directory can not be created where a file is
+    let file = cassette_dir("file");
+    std::fs::write(&file, "").expect("must write the file");
+    let recorder = HnClient::builder()
+        .base_url(mock_hn.base_url())
+        .cassette(Cassette::record(&file))
+        .build()
+        .expect("must build the client");
+    let result = recorder.get_item(8863).await;
+    assert!(matches!(result, Err(ServerError::RecordFailed(_))));
+    std::fs::remove_file(&file).expect("must remove the file");
+}
@@ -0,0 +1,70 @@
+#![deny(warnings)]
+//! The requests to the `mock-hn` api are limited and the simultaneous ones are shared
+use common::{
+    api::HnClient,
+    types::{StoryOrder, StorySorting},
+};
+use futures::future::join_all;
+use mock_hn::MockHn;
+use std::time::Duration;
+
+/// the mock api is slow, so the requests to it overlap
+const LATENCY: Duration = Duration::from_millis(100);
+
+#[tokio::test]
+async fn simultaneous_requests_share_one_request() {
+    let mock_hn = MockHn::start_with_latency(LATENCY);
+    let client =
+        HnClient::new(mock_hn.base_url()).expect("must build the client");
+    let items = join_all((0..10).map(|_| client.get_item(8863))).await;
+    assert!(items.iter().all(|item| item.is_ok()));
+    assert_eq!(mock_hn.requests(), 1);
+
+    // the finished request is not shared anymore
+    client.get_item(8863).await.expect("must get the item");
+    assert_eq!(mock_hn.requests(), 2);
+}
+
+#[tokio::test]
+async fn requests_in_flight_are_limited() {
+    let mock_hn = MockHn::start_with_latency(LATENCY);
+    let client = HnClient::builder()
+        .base_url(mock_hn.base_url())
+        .max_in_flight(2)
+        .build()
+        .expect("must build the client");
+    let story_list = client
+        .get_stories_with_sorting(StorySorting::New, 1, StoryOrder::Rank)
+        .await
+        .expect("must get the stories");
+    assert_eq!(story_list.stories.len(), 3);
+    // the story ids and then the 3 stories
+    assert_eq!(mock_hn.requests(), 4);
+    assert_eq!(mock_hn.max_in_flight(), 2);
+}
+
+#[tokio::test]
+async fn dropped_requests_give_back_their_turn() {
+    let mock_hn = MockHn::start_with_latency(LATENCY);
+    let client = HnClient::builder()
+        .base_url(mock_hn.base_url())
+        .max_in_flight(1)
+        .build()
+        .expect("must build the client");
+    // give up on the request while it is in flight, ie: the visitor went away
+    let given_up =
+        tokio::time::timeout(LATENCY / 2, client.get_item(8863)).await;
+    assert!(given_up.is_err());
+    assert_eq!(mock_hn.requests(), 1);
+
+    // the only turn is not held by the dropped request anymore
+    let item = tokio::time::timeout(LATENCY * 5, client.get_item(9224))
+        .await
+        .expect("must not wait for the dropped request");
+    assert!(item.is_ok());
+    // the dropped request is made again, rather than waited for forever
+    let item = tokio::time::timeout(LATENCY * 5, client.get_item(8863))
+        .await
+        .expect("must not wait for the dropped request");
+    assert!(item.is_ok());
+}
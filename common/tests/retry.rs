@@ -0,0 +1,106 @@
+#![deny(warnings)]
+//! The requests to the `mock-hn` api which fail for a transient reason are retried,
+//! and the items which still failed to load are reported
+use common::{
+    api::{HnClient, RetryPolicy, ServerError},
+    types::{StoryOrder, StorySorting},
+};
+use futures::future::join;
+use mock_hn::MockHn;
+use std::time::Duration;
+
+/// a client which retries twice, without waiting long between the retries
+fn client(mock_hn: &MockHn) -> HnClient {
+    HnClient::builder()
+        .base_url(mock_hn.base_url())
+        .retry(RetryPolicy {
+            max_retries: 2,
+            base_delay: Duration::from_millis(10),
+            max_delay: Duration::from_millis(50),
+        })
+        .build()
+        .expect("must build the client")
+}
+
+#[tokio::test]
+async fn transient_failures_are_retried() {
+    let mock_hn = MockHn::start();
+    mock_hn.fail_next(2, 503);
+    let item = client(&mock_hn).get_item(8863).await;
+    assert!(item.is_ok());
+    assert_eq!(mock_hn.requests(), 3);
+}
+
+#[tokio::test]
+async fn failures_past_the_retries_are_the_upstream_status() {
+    let mock_hn = MockHn::start();
+    mock_hn.fail_next(3, 503);
+    let item = client(&mock_hn).get_item(8863).await;
+    assert!(matches!(item, Err(ServerError::UpstreamStatus(503))));
+    assert_eq!(mock_hn.requests(), 3);
+}
+
+#[tokio::test]
+async fn client_errors_are_not_retried() {
+    let mock_hn = MockHn::start();
+    mock_hn.fail_next(1, 403);
+    let item = client(&mock_hn).get_item(8863).await;
+    assert!(matches!(item, Err(ServerError::UpstreamStatus(403))));
+    let item = client(&mock_hn).get_item(1).await;
+    assert!(matches!(item, Err(ServerError::NotFound)));
+    assert_eq!(mock_hn.requests(), 2);
+}
+
+#[tokio::test]
+async fn stories_which_failed_are_reported_with_the_listing() {
+    let mock_hn = MockHn::start_with_latency(Duration::from_millis(50));
+    let client = HnClient::builder()
+        .base_url(mock_hn.base_url())
+        .max_in_flight(1)
+        .build()
+        .expect("must build the client");
+    let fetch_stories =
+        client.get_stories_with_sorting(StorySorting::Top, 1, StoryOrder::Rank);
+    // fail the first story, which is the only request in flight
+    // once the listing of the story ids is done
+    let fail_first_story = async {
+        while mock_hn.requests() < 2 {
+            tokio::time::sleep(Duration::from_millis(5)).await;
+        }
+        mock_hn.fail_next(1, 403);
+    };
+    let (story_list, ()) = join(fetch_stories, fail_first_story).await;
+    let story_list = story_list.expect("must get the stories");
+    assert_eq!(story_list.stories.len(), 2);
+    assert_eq!(story_list.failed.len(), 1);
+    let failed = &story_list.failed[0];
+    assert_eq!(failed.code, "upstream_status");
+    assert!(story_list.stories.iter().all(|story| story.id != failed.id));
+}
+
+#[tokio::test]
+async fn slow_requests_time_out() {
+    let mock_hn = MockHn::start_with_latency(Duration::from_millis(500));
+    let client = HnClient::builder()
+        .base_url(mock_hn.base_url())
+        .timeout(Duration::from_millis(50))
+        .retry(RetryPolicy::none())
+        .build()
+        .expect("must build the client");
+    let item = client.get_item(8863).await;
+    assert!(matches!(item, Err(ServerError::Timeout)));
+}
+
+#[test]
+fn backoff_is_exponential_with_jitter() {
+    let retry = RetryPolicy {
+        max_retries: 5,
+        base_delay: Duration::from_millis(100),
+        max_delay: Duration::from_millis(1000),
+    };
+    for (attempt, backoff) in [100, 200, 400, 800, 1000].iter().enumerate() {
+        let backoff = Duration::from_millis(*backoff);
+        let delay = retry.delay(attempt as u32);
+        assert!(delay >= backoff / 2 && delay <= backoff, "{:?}", delay);
+    }
+}
@@ -0,0 +1,61 @@
+#![deny(warnings)]
+//! The snapshot of the `mock-hn` api has the same stories as the api itself
+use common::{
+    api::HnClient,
+    snapshot::Snapshot,
+    types::CommentFetchOptions,
+};
+use mock_hn::MockHn;
+
+lazy_static::lazy_static! {
+    static ref MOCK_HN: MockHn = MockHn::start();
+}
+
+/// take a snapshot of the api, which must have the same story as the api itself
+async fn snapshot_has_the_live_story(options: &CommentFetchOptions) -> Snapshot {
+    let live =
+        HnClient::new(MOCK_HN.base_url()).expect("must build the client");
+    let snapshot = Snapshot::create(&live, 1, options)
+        .await
+        .expect("must take the snapshot");
+    let from_snapshot = HnClient::builder()
+        .snapshot(snapshot.clone())
+        .build()
+        .expect("must build the client");
+    let live_story = live
+        .get_story_with_options(8863, options)
+        .await
+        .expect("must get the story");
+    let snapshot_story = from_snapshot
+        .get_story_with_options(8863, options)
+        .await
+        .expect("must get the story from the snapshot");
+    assert_eq!(snapshot_story, live_story);
+    snapshot
+}
+
+#[tokio::test]
+async fn snapshot_has_the_replies_down_to_the_depth() {
+    let options = CommentFetchOptions {
+        max_depth: 1,
+        ..Default::default()
+    };
+    let snapshot = snapshot_has_the_live_story(&options).await;
+    // the replies to the top-level comments
+    assert!(snapshot.items.contains_key(&9272));
+    assert!(snapshot.items.contains_key(&8952));
+    snapshot_has_the_live_story(&CommentFetchOptions::default()).await;
+}
+
+#[tokio::test]
+async fn snapshot_has_at_most_the_total_comments() {
+    let options = CommentFetchOptions {
+        max_total: 1,
+        ..Default::default()
+    };
+    let snapshot = snapshot_has_the_live_story(&options).await;
+    // only the first top-level comment of the story
+    assert!(snapshot.items.contains_key(&9224));
+    assert!(!snapshot.items.contains_key(&8917));
+    assert!(!snapshot.items.contains_key(&9272));
+}
@@ -0,0 +1,178 @@
+#![deny(warnings)]
+//! A stand-in for the hacker news api which serves the recorded json fixtures,
+//! so the api client and the server can be run and tested with no network.
+//!
+//! The fixtures are laid out as the urls of the api, ie: `fixtures/v0/item/8863.json`
+//! is served at `/v0/item/8863.json`. Just like the api, the items and users
+//! which are not in the fixtures are `null`.
+//!
+//! The mock api counts the requests it gets, so the tests can tell how many were made,
+//! and it can be made to respond slowly to keep the requests in flight for a while,
+//! or to fail the next few requests with an error status.
+use axum::{
+    handler::Handler,
+    http::{header, HeaderMap, HeaderValue, StatusCode, Uri},
+    response::{IntoResponse, Response},
+    Router,
+};
+use std::{
+    net::{SocketAddr, TcpListener},
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicU16, AtomicUsize, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+/// the fixtures recorded from the api
+pub const FIXTURES_DIR: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/fixtures");
+
+/// the router which serves the fixtures in this directory
+pub fn router(fixtures_dir: impl Into<PathBuf>) -> Router {
+    counted_router(
+        fixtures_dir.into(),
+        Duration::default(),
+        Arc::new(Counts::default()),
+    )
+}
+
+/// the router which serves the fixtures after the latency, counting the requests
+fn counted_router(
+    fixtures_dir: PathBuf,
+    latency: Duration,
+    counts: Arc<Counts>,
+) -> Router {
+    let serve_fixture = move |uri: Uri| {
+        let fixtures_dir = fixtures_dir.clone();
+        let counts = Arc::clone(&counts);
+        async move {
+            counts.requests.fetch_add(1, Ordering::SeqCst);
+            let in_flight = counts.in_flight.fetch_add(1, Ordering::SeqCst) + 1;
+            counts.max_in_flight.fetch_max(in_flight, Ordering::SeqCst);
+            if latency > Duration::default() {
+                tokio::time::sleep(latency).await;
+            }
+            counts.in_flight.fetch_sub(1, Ordering::SeqCst);
+            let failed = counts
+                .failures
+                .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |failures| {
+                    failures.checked_sub(1)
+                })
+                .is_ok();
+            if failed {
+                let status = StatusCode::from_u16(
+                    counts.failure_status.load(Ordering::SeqCst),
+                )
+                .unwrap_or(StatusCode::INTERNAL_SERVER_ERROR);
+                return (status, "failed on purpose").into_response();
+            }
+            fixture(&fixtures_dir, uri.path())
+        }
+    };
+    Router::new().fallback(serve_fixture.into_service())
+}
+
+#[derive(Default)]
+struct Counts {
+    /// all the requests so far
+    requests: AtomicUsize,
+    /// the requests which are being responded to
+    in_flight: AtomicUsize,
+    /// the most requests which were in flight at once
+    max_in_flight: AtomicUsize,
+    /// the next requests which fail with the `failure_status`
+    failures: AtomicUsize,
+    failure_status: AtomicU16,
+}
+
+fn fixture(fixtures_dir: &Path, path: &str) -> Response {
+    let path = path.trim_start_matches('/');
+    // only the json files inside the fixtures directory are served
+    if !path.ends_with(".json")
+        || path
+            .split('/')
+            .any(|segment| segment.is_empty() || segment == "..")
+    {
+        return (StatusCode::NOT_FOUND, "not found").into_response();
+    }
+    let json = match std::fs::read(fixtures_dir.join(path)) {
+        Ok(json) => json,
+        Err(_) => b"null".to_vec(),
+    };
+    let mut headers = HeaderMap::new();
+    headers.insert(
+        header::CONTENT_TYPE,
+        HeaderValue::from_static("application/json"),
+    );
+    (headers, json).into_response()
+}
+
+/// The mock api running in the background on its own thread and runtime,
+/// so it outlives the runtime of any single test which uses it.
+pub struct MockHn {
+    addr: SocketAddr,
+    counts: Arc<Counts>,
+}
+
+impl MockHn {
+    /// serve the recorded fixtures on a free port of localhost
+    pub fn start() -> Self {
+        Self::start_with(FIXTURES_DIR, Duration::default())
+    }
+
+    /// serve the recorded fixtures, each after this latency
+    pub fn start_with_latency(latency: Duration) -> Self {
+        Self::start_with(FIXTURES_DIR, latency)
+    }
+
+    /// serve the fixtures in this directory on a free port of localhost,
+    /// each after this latency
+    pub fn start_with(fixtures_dir: impl Into<PathBuf>, latency: Duration) -> Self {
+        let counts = Arc::new(Counts::default());
+        let router =
+            counted_router(fixtures_dir.into(), latency, Arc::clone(&counts));
+        let listener = TcpListener::bind("127.0.0.1:0")
+            .expect("must bind to a free port");
+        let addr = listener.local_addr().expect("must have an address");
+        std::thread::spawn(move || {
+            let runtime = tokio::runtime::Builder::new_current_thread()
+                .enable_all()
+                .build()
+                .expect("must build the runtime");
+            runtime.block_on(async move {
+                axum::Server::from_tcp(listener)
+                    .expect("must listen")
+                    .serve(router.into_make_service())
+                    .await
+                    .expect("must serve the fixtures")
+            });
+        });
+        Self { addr, counts }
+    }
+
+    pub fn addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    /// the number of requests the mock api got so far
+    pub fn requests(&self) -> usize {
+        self.counts.requests.load(Ordering::SeqCst)
+    }
+
+    /// the most requests which were in flight at once so far
+    pub fn max_in_flight(&self) -> usize {
+        self.counts.max_in_flight.load(Ordering::SeqCst)
+    }
+
+    /// respond to the next `count` requests with this error status, ie: 503
+    pub fn fail_next(&self, count: usize, status: u16) {
+        self.counts.failure_status.store(status, Ordering::SeqCst);
+        self.counts.failures.store(count, Ordering::SeqCst);
+    }
+
+    /// the base url to point the api client to, ie: `http://127.0.0.1:41234/v0`
+    pub fn base_url(&self) -> String {
+        format!("http://{}/v0", self.addr)
+    }
+}
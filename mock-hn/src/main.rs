@@ -0,0 +1,23 @@
+#![deny(warnings)]
+//! Serve the fixtures, or the ones in the directory passed as the argument,
+//! to point the server to with `HN_API_BASE_URL=http://localhost:3031/v0`
+use std::net::SocketAddr;
+
+const DEFAULT_PORT: u16 = 3031;
+
+#[tokio::main]
+async fn main() {
+    let fixtures_dir = std::env::args()
+        .nth(1)
+        .unwrap_or_else(|| mock_hn::FIXTURES_DIR.to_string());
+    let port = std::env::var("PORT")
+        .ok()
+        .and_then(|port| port.parse::<u16>().ok())
+        .unwrap_or(DEFAULT_PORT);
+    let socket: SocketAddr = ([127, 0, 0, 1], port).into();
+    println!("serving the fixtures in {} at: http://{}/v0", fixtures_dir, socket);
+    axum::Server::bind(&socket)
+        .serve(mock_hn::router(fixtures_dir).into_make_service())
+        .await
+        .unwrap();
+}
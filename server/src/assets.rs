@@ -0,0 +1,184 @@
+//! The static assets of the client, ie: the stylesheet, the favicons and the wasm package.
+//!
+//! With the `embed-assets` feature the assets are embedded into the server binary,
+//! otherwise they are read at startup from the `client` directory, or the one in `HN_ASSETS_DIR`,
+//! so the server can be built before the client is.
+//!
+//! Each asset is served at its path with an `ETag` to be revalidated,
+//! and at a fingerprinted path with the hash of its content, ie: `/pkg/client_bg.1a2b3c4d5e6f7a8b.wasm`,
+//! which browsers cache for good.
+use axum::{
+    http::{header, HeaderMap, HeaderValue, StatusCode},
+    response::{IntoResponse, Response},
+    routing::get,
+    Router,
+};
+use crate::conditional::etag_matches;
+use sha2::{Digest, Sha256};
+use std::borrow::Cow;
+
+/// the directory the assets are read from, when they are not embedded
+#[cfg(not(feature = "embed-assets"))]
+const DEFAULT_ASSETS_DIR: &str = "client";
+
+/// the paths of the assets, relative to the assets directory
+#[cfg(not(feature = "embed-assets"))]
+const ASSET_PATHS: [&str; 5] = [
+    "favicon.ico",
+    "favicon.svg",
+    "style.css",
+    "pkg/client.js",
+    "pkg/client_bg.wasm",
+];
+
+/// the fingerprinted assets never change, so they are cached for a year
+const IMMUTABLE: &str = "public, max-age=31536000, immutable";
+
+lazy_static::lazy_static! {
+    static ref ASSETS: Vec<Asset> = load();
+}
+
+pub struct Asset {
+    /// the path relative to the assets directory, ie: `pkg/client.js`
+    pub path: &'static str,
+    pub bytes: Cow<'static, [u8]>,
+    /// the etag derived from the content, which is weak
+    /// since the same etag is sent with each of the encodings of the compression
+    pub etag: String,
+    /// the path with the hash of the content, ie: `pkg/client.1a2b3c4d5e6f7a8b.js`
+    pub fingerprinted_path: String,
+}
+
+impl Asset {
+    fn new(path: &'static str, bytes: Cow<'static, [u8]>) -> Self {
+        let hash = Sha256::digest(&bytes)
+            .iter()
+            .map(|b| format!("{:02x}", b))
+            .collect::<String>();
+        let fingerprinted_path = match path.rfind('.') {
+            Some(dot) => {
+                format!("{}.{}{}", &path[..dot], &hash[..16], &path[dot..])
+            }
+            None => format!("{}.{}", path, &hash[..16]),
+        };
+        Self {
+            path,
+            bytes,
+            etag: format!("W/\"{}\"", &hash[..32]),
+            fingerprinted_path,
+        }
+    }
+
+    pub fn content_type(&self) -> &'static str {
+        match self.path.rsplit('.').next() {
+            Some("ico") => "image/x-icon",
+            Some("svg") => "image/svg+xml",
+            Some("css") => "text/css",
+            Some("js") => "text/javascript",
+            Some("wasm") => "application/wasm",
+            _ => "application/octet-stream",
+        }
+    }
+
+    /// respond with the asset, or with a 304 when the client already has it
+    fn response(
+        &self,
+        request_headers: &HeaderMap,
+        cache_control: &'static str,
+    ) -> Response {
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            header::ETAG,
+            HeaderValue::from_str(&self.etag).expect("must be a valid etag"),
+        );
+        headers.insert(
+            header::CACHE_CONTROL,
+            HeaderValue::from_static(cache_control),
+        );
+        if etag_matches(request_headers, &self.etag) {
+            return (StatusCode::NOT_MODIFIED, headers, ()).into_response();
+        }
+        headers.insert(
+            header::CONTENT_TYPE,
+            HeaderValue::from_static(self.content_type()),
+        );
+        (headers, self.bytes.to_vec()).into_response()
+    }
+}
+
+#[cfg(feature = "embed-assets")]
+fn load() -> Vec<Asset> {
+    let embedded: [(&'static str, &'static [u8]); 5] = [
+        ("favicon.ico", include_bytes!("../../client/favicon.ico")),
+        ("favicon.svg", include_bytes!("../../client/favicon.svg")),
+        ("style.css", include_bytes!("../../client/style.css")),
+        (
+            "pkg/client.js",
+            include_bytes!("../../client/pkg/client.js"),
+        ),
+        (
+            "pkg/client_bg.wasm",
+            include_bytes!("../../client/pkg/client_bg.wasm"),
+        ),
+    ];
+    embedded
+        .iter()
+        .map(|(path, bytes)| Asset::new(*path, Cow::Borrowed(*bytes)))
+        .collect()
+}
+
+#[cfg(not(feature = "embed-assets"))]
+fn load() -> Vec<Asset> {
+    let dir = std::env::var("HN_ASSETS_DIR")
+        .unwrap_or_else(|_| DEFAULT_ASSETS_DIR.to_string());
+    ASSET_PATHS
+        .iter()
+        .filter_map(|path| {
+            let file = std::path::Path::new(&dir).join(path);
+            match std::fs::read(&file) {
+                Ok(bytes) => Some(Asset::new(*path, Cow::Owned(bytes))),
+                Err(e) => {
+                    eprintln!(
+                        "asset {} is not available: {}",
+                        file.display(),
+                        e
+                    );
+                    None
+                }
+            }
+        })
+        .collect()
+}
+
+/// all the assets which are available
+pub fn all() -> &'static [Asset] {
+    &ASSETS
+}
+
+/// the url to link to this asset, which is fingerprinted when the asset is available
+pub fn url(path: &str) -> String {
+    match all().iter().find(|asset| asset.path == path) {
+        Some(asset) => format!("/{}", asset.fingerprinted_path),
+        None => format!("/{}", path),
+    }
+}
+
+/// add the routes to each of the assets, at their path and at their fingerprinted path
+pub fn routes(mut router: Router) -> Router {
+    for asset in all() {
+        router = router
+            .route(
+                &format!("/{}", asset.path),
+                get(move |headers: HeaderMap| async move {
+                    asset.response(&headers, "no-cache")
+                }),
+            )
+            .route(
+                &format!("/{}", asset.fingerprinted_path),
+                get(move |headers: HeaderMap| async move {
+                    asset.response(&headers, IMMUTABLE)
+                }),
+            );
+    }
+    router
+}
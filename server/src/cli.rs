@@ -0,0 +1,66 @@
+//! The commands of the server binary
+use common::snapshot::SNAPSHOT_FILE;
+use std::path::PathBuf;
+
+/// the pages of each listing which are taken into a snapshot by default
+const DEFAULT_SNAPSHOT_PAGES: usize = 3;
+
+/// the pages of each listing which are exported by default
+const DEFAULT_EXPORT_PAGES: usize = 1;
+
+/// the directory the pages are exported to by default
+const DEFAULT_EXPORT_DIR: &str = "export";
+
+pub const USAGE: &str = "\
+usage:
+    server [serve]                              serve the app
+    server snapshot [--out FILE] [--pages N]    write a snapshot of the first N pages of every listing
+    server export [--out DIR] [--pages N]       pre-render the first N pages of every listing into html files";
+
+pub enum Command {
+    /// serve the app, this is the default
+    Serve,
+    /// write a snapshot of the first `pages` of every listing to `out`
+    Snapshot { out: PathBuf, pages: usize },
+    /// pre-render the first `pages` of every listing and the pages linked from them into `out`
+    Export { out: PathBuf, pages: usize },
+}
+
+impl Command {
+    pub fn from_args() -> Result<Self, String> {
+        let args: Vec<String> = std::env::args().skip(1).collect();
+        match args.first().map(String::as_str) {
+            None | Some("serve") => Ok(Command::Serve),
+            Some("snapshot") => Ok(Command::Snapshot {
+                out: PathBuf::from(flag(&args, "--out").unwrap_or(SNAPSHOT_FILE)),
+                pages: pages(&args, DEFAULT_SNAPSHOT_PAGES)?,
+            }),
+            Some("export") => Ok(Command::Export {
+                out: PathBuf::from(
+                    flag(&args, "--out").unwrap_or(DEFAULT_EXPORT_DIR),
+                ),
+                pages: pages(&args, DEFAULT_EXPORT_PAGES)?,
+            }),
+            Some(command) => Err(format!("unknown command: {}", command)),
+        }
+    }
+}
+
+/// the value following this flag in the args, ie: `--out snapshot.json`
+fn flag<'a>(args: &'a [String], name: &str) -> Option<&'a str> {
+    args.iter()
+        .position(|arg| arg == name)
+        .and_then(|i| args.get(i + 1))
+        .map(String::as_str)
+}
+
+/// the number of `--pages`, at least 1
+fn pages(args: &[String], default: usize) -> Result<usize, String> {
+    match flag(args, "--pages") {
+        Some(pages) => match pages.parse() {
+            Ok(pages) if pages > 0 => Ok(pages),
+            _ => Err(format!("invalid --pages: {}", pages)),
+        },
+        None => Ok(default),
+    }
+}
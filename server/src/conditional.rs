@@ -0,0 +1,161 @@
+//! Conditional GET of the rendered pages and the json api.
+//!
+//! The responses are tagged with an `ETag` of their content, so the clients which already
+//! have the same response get a 304 instead.
+//! The `Last-Modified` of a url is the time its etag was first served, rather than the time of
+//! the items in it, since the items, ie: their score or their rank, change without a newer item.
+use axum::{
+    http::{header, HeaderMap, HeaderValue, StatusCode, Uri},
+    response::{IntoResponse, Response},
+};
+use common::types::ApiResponse;
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use std::{
+    sync::Mutex,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+/// the number of urls the time of their last change is kept for
+const MODIFIED_CAPACITY: usize = 10_000;
+
+lazy_static::lazy_static! {
+    /// the etag last served at each url, along with the time it was first served
+    static ref MODIFIED: Mutex<lru::LruCache<String, (String, SystemTime)>> =
+        Mutex::new(lru::LruCache::new(MODIFIED_CAPACITY));
+}
+
+/// respond with the rendered page, or with a 304 when the client has it already
+pub fn html(uri: &Uri, request_headers: &HeaderMap, html: String) -> Response {
+    content(
+        uri,
+        request_headers,
+        "text/html; charset=utf-8",
+        html.into_bytes(),
+    )
+}
+
+/// respond with the content of this type, or with a 304 when the client has it already
+pub fn content(
+    uri: &Uri,
+    request_headers: &HeaderMap,
+    content_type: &'static str,
+    body: Vec<u8>,
+) -> Response {
+    let hash = content_hash(&body);
+    respond(uri, request_headers, content_type, body, &hash)
+}
+
+/// respond with the json api response, or with a 304 when the client has it already.
+/// The etag is derived from the data alone, since the `fetched_at` differs in every response.
+pub fn json<T: Serialize>(
+    uri: &Uri,
+    request_headers: &HeaderMap,
+    response: &ApiResponse<T>,
+) -> Response {
+    let data = serde_json::to_vec(&(&response.data, &response.pagination))
+        .expect("must serialize");
+    let json = serde_json::to_vec(response).expect("must serialize");
+    respond(
+        uri,
+        request_headers,
+        "application/json",
+        json,
+        &content_hash(&data),
+    )
+}
+
+/// the hex of the sha256 of the content
+fn content_hash(content: &[u8]) -> String {
+    Sha256::digest(content)
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect::<String>()
+}
+
+fn respond(
+    uri: &Uri,
+    request_headers: &HeaderMap,
+    content_type: &'static str,
+    body: Vec<u8>,
+    hash: &str,
+) -> Response {
+    // the etag is weak, since the body is transformed by the compression
+    let etag = format!("W/\"{}\"", &hash[..32]);
+    let last_modified = last_modified(uri, &etag);
+
+    let mut headers = HeaderMap::new();
+    headers.insert(
+        header::ETAG,
+        HeaderValue::from_str(&etag).expect("must be a valid etag"),
+    );
+    headers.insert(
+        header::LAST_MODIFIED,
+        HeaderValue::from_str(&httpdate::fmt_http_date(last_modified))
+            .expect("must be a valid date"),
+    );
+    // the pages are always revalidated, as the items change over time
+    headers.insert(header::CACHE_CONTROL, HeaderValue::from_static("no-cache"));
+    if is_not_modified(request_headers, &etag, last_modified) {
+        return (StatusCode::NOT_MODIFIED, headers, ()).into_response();
+    }
+    headers.insert(
+        header::CONTENT_TYPE,
+        HeaderValue::from_static(content_type),
+    );
+    (headers, body).into_response()
+}
+
+/// the time the content with this etag was first served at this url,
+/// which is now when the url served another etag before
+fn last_modified(uri: &Uri, etag: &str) -> SystemTime {
+    let mut modified = MODIFIED.lock().unwrap();
+    let url = uri.to_string();
+    if let Some((served, since)) = modified.get(&url) {
+        if served == etag {
+            return *since;
+        }
+    }
+    // the http dates are in whole seconds
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|now| UNIX_EPOCH + Duration::from_secs(now.as_secs()))
+        .unwrap_or_else(|_| SystemTime::now());
+    modified.put(url, (etag.to_string(), now));
+    now
+}
+
+/// whether the client has the response already, from the `If-None-Match` of the request,
+/// or its `If-Modified-Since` when there is no `If-None-Match`
+fn is_not_modified(
+    request_headers: &HeaderMap,
+    etag: &str,
+    last_modified: SystemTime,
+) -> bool {
+    if request_headers.contains_key(header::IF_NONE_MATCH) {
+        return etag_matches(request_headers, etag);
+    }
+    request_headers
+        .get(header::IF_MODIFIED_SINCE)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| httpdate::parse_http_date(value).ok())
+        // a date later than now is invalid, and is ignored
+        .filter(|modified_since| *modified_since <= SystemTime::now())
+        .map(|modified_since| last_modified <= modified_since)
+        .unwrap_or(false)
+}
+
+/// whether the `If-None-Match` of the request matches this etag,
+/// weak and strong etags are compared alike
+pub fn etag_matches(request_headers: &HeaderMap, etag: &str) -> bool {
+    let etag = etag.trim_start_matches("W/");
+    request_headers
+        .get(header::IF_NONE_MATCH)
+        .and_then(|value| value.to_str().ok())
+        .map(|value| {
+            value.split(',').map(str::trim).any(|tag| {
+                tag == "*" || tag.trim_start_matches("W/") == etag
+            })
+        })
+        .unwrap_or(false)
+}
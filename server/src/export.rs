@@ -0,0 +1,313 @@
+//! Export the pages of the app as static html files, along with the assets,
+//! so the app can be browsed on a static hosting site, even without javascript.
+//!
+//! Each page is written to the `index.html` in the directory of its url,
+//! ie: `/item/123` is written to `item/123/index.html`.
+//! The url of a user page leaves the characters of the usernames allowed by hacker news as is,
+//! so it is also the directory the page is written to, ie: `/user/foo_bar` is written to `user/foo_bar/index.html`.
+use crate::{
+    assets,
+    page,
+};
+use client::App;
+use common::{
+    api,
+    route::Route,
+    types::{
+        Comment,
+        CommentFetchOptions,
+        StoryOrder,
+        StorySorting,
+    },
+};
+use futures::{
+    stream,
+    StreamExt,
+};
+use std::{
+    collections::BTreeSet,
+    fs,
+    future::Future,
+    io,
+    path::{
+        Path,
+        PathBuf,
+    },
+};
+
+/// the number of pages which are fetched at the same time
+const CONCURRENCY: usize = 8;
+
+/// Export the first `pages` of every listing, the stories in them along with their comments,
+/// and the users who submitted them into the `out` directory.
+pub async fn export(out: &Path, pages: usize) -> io::Result<()> {
+    let mut exporter = Exporter {
+        out: out.to_path_buf(),
+        pages,
+        rendered: vec![],
+    };
+    exporter.write_assets()?;
+    // the static hosts serve it at any path which is not exported
+    exporter.add_page("/404.html", &App::with_not_found(""));
+
+    let mut stories = BTreeSet::new();
+    let mut users = BTreeSet::new();
+    for sorting in StorySorting::all() {
+        for page in 1..=pages {
+            let story_list = match api::get_stories_with_sorting(
+                sorting,
+                page,
+                StoryOrder::Rank,
+            )
+            .await
+            {
+                Ok(story_list) => story_list,
+                Err(e) => {
+                    eprintln!("skipping {}: {}", sorting.to_page_url(page), e);
+                    break;
+                }
+            };
+            let is_last_page = story_list.next_page().is_none();
+            for story in story_list.stories.iter() {
+                stories.insert(story.id);
+                if !story.by.is_empty() {
+                    users.insert(story.by.clone());
+                }
+            }
+            let app = App::with_stories(story_list);
+            let url = exporter.page_url(sorting, page);
+            exporter.add_page(&url, &app);
+            if sorting == StorySorting::default() && page == 1 {
+                exporter.add_page("/", &app);
+            }
+            if is_last_page {
+                break;
+            }
+        }
+    }
+
+    let mut comments = BTreeSet::new();
+    let story_pages = exporter
+        .fetch_all(stories, |story_id| async move {
+            api::get_story_with_options(
+                story_id,
+                &CommentFetchOptions::default(),
+            )
+            .await
+        })
+        .await;
+    for story_page in story_pages {
+        collect_comments(&story_page.comments, &mut comments, &mut users);
+        let url = Route::Item {
+            id: story_page.id,
+            options: CommentFetchOptions::default(),
+        }
+        .to_url();
+        exporter.add_page(&url, &App::with_story(story_page));
+    }
+
+    let comment_permalinks = exporter
+        .fetch_all(comments, |comment_id| async move {
+            api::get_comment(comment_id).await
+        })
+        .await;
+    for comment in comment_permalinks {
+        let url = Route::Comment(comment.id).to_url();
+        exporter.add_page(&url, &App::with_comment_permalink(comment));
+    }
+
+    let user_pages = exporter
+        .fetch_all(users, |username| async move {
+            api::get_user_page(&username).await
+        })
+        .await;
+    for user_page in user_pages {
+        if !is_file_name(&user_page.id) {
+            eprintln!("skipping the user {:?}: not a file name", user_page.id);
+            continue;
+        }
+        let url = Route::User(user_page.id.clone()).to_url();
+        exporter.add_page(&url, &App::with_user_page(user_page));
+    }
+    exporter.write_pages()
+}
+
+struct Exporter {
+    out: PathBuf,
+    pages: usize,
+    /// the url and the html of each of the pages, which are written once all of them are rendered,
+    /// so the links to the pages which are not exported can be told apart
+    rendered: Vec<(String, String)>,
+}
+
+impl Exporter {
+    /// write the assets at their path and at their fingerprinted path which the pages link to
+    fn write_assets(&self) -> io::Result<()> {
+        for asset in assets::all() {
+            self.write_file(asset.path, &asset.bytes)?;
+            self.write_file(&asset.fingerprinted_path, &asset.bytes)?;
+        }
+        Ok(())
+    }
+
+    /// the url of this page of the listing in the export.
+    /// The query of `/top?p=2` is ignored by static hosting,
+    /// so the pages after the first are exported as `/top/page/2`
+    fn page_url(&self, sorting: StorySorting, page: usize) -> String {
+        if page > 1 {
+            format!("{}/page/{}", sorting.to_url(), page)
+        } else {
+            sorting.to_url()
+        }
+    }
+
+    /// the url this page is exported at, which has no query
+    fn export_url(&self, route: &Route) -> String {
+        match route {
+            Route::Stories { sorting, page } => self.page_url(*sorting, *page),
+            _ => route.to_url(),
+        }
+    }
+
+    /// render the app into the page at this url
+    fn add_page(&mut self, url: &str, app: &App) {
+        let html = page::index(app).render_to_string();
+        self.rendered.push((url.to_string(), html));
+    }
+
+    /// write each of the pages into the `index.html` in the directory of its url,
+    /// or into the file itself when the url is of an html file
+    fn write_pages(&self) -> io::Result<()> {
+        let exported = self
+            .rendered
+            .iter()
+            .map(|(url, _)| url.clone())
+            .collect::<BTreeSet<_>>();
+        for (url, html) in self.rendered.iter() {
+            let html = self.link_pages(html, &exported);
+            let path = url.trim_start_matches('/');
+            if path.ends_with(".html") {
+                self.write_file(path, html.as_bytes())?;
+            } else {
+                self.write_file(
+                    &Path::new(path).join("index.html").to_string_lossy(),
+                    html.as_bytes(),
+                )?;
+            }
+        }
+        Ok(())
+    }
+
+    /// point the links to the pages of the app to where they are exported,
+    /// and drop the links to the pages which are not exported,
+    /// ie: the next page of the last exported page, or `/item/123?children=100`
+    fn link_pages(&self, html: &str, exported: &BTreeSet<String>) -> String {
+        const HREF: &str = "href=\"";
+        let mut linked = String::with_capacity(html.len());
+        let mut rest = html;
+        while let Some(start) = rest.find(HREF) {
+            let value = &rest[start + HREF.len()..];
+            let end = match value.find('"') {
+                Some(end) => end,
+                None => break,
+            };
+            linked.push_str(&rest[..start]);
+            if let Some(link) = self.link(&value[..end], exported) {
+                linked.push_str(HREF);
+                linked.push_str(&link);
+                linked.push('"');
+            }
+            rest = &value[end + 1..];
+        }
+        linked.push_str(rest);
+        linked
+    }
+
+    /// where the link to `href` points to in the export,
+    /// or `None` when it is a page of the app which is not exported
+    fn link(&self, href: &str, exported: &BTreeSet<String>) -> Option<String> {
+        if !href.starts_with('/')
+            || href.starts_with("//")
+            || exported.contains(href)
+        {
+            return Some(href.to_string());
+        }
+        match Route::parse(&href.replace("&amp;", "&")) {
+            Some(route) => {
+                let url = self.export_url(&route);
+                if exported.contains(&url) {
+                    Some(format!("{}/", url))
+                } else {
+                    None
+                }
+            }
+            // the assets
+            None => Some(href.to_string()),
+        }
+    }
+
+    fn write_file(&self, path: &str, bytes: &[u8]) -> io::Result<()> {
+        let file = self.out.join(path);
+        if let Some(dir) = file.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        println!("writing: {}", file.display());
+        fs::write(file, bytes)
+    }
+
+    /// fetch the page of each of the `keys`, a few at a time.
+    /// The pages which failed to be fetched are skipped.
+    async fn fetch_all<K, T, F, Fut>(
+        &self,
+        keys: BTreeSet<K>,
+        fetch: F,
+    ) -> Vec<T>
+    where
+        K: std::fmt::Debug + Clone,
+        F: Fn(K) -> Fut,
+        Fut: Future<Output = Result<T, api::ServerError>>,
+    {
+        stream::iter(keys)
+            .map(|key| {
+                let fetched = fetch(key.clone());
+                async move { (key, fetched.await) }
+            })
+            .buffer_unordered(CONCURRENCY)
+            .filter_map(|(key, fetched)| async move {
+                match fetched {
+                    Ok(fetched) => Some(fetched),
+                    Err(e) => {
+                        eprintln!("skipping {:?}: {}", key, e);
+                        None
+                    }
+                }
+            })
+            .collect()
+            .await
+    }
+}
+
+/// whether the username is left as is in its url, and can be the name of a directory,
+/// which is the case of all the usernames allowed by hacker news
+fn is_file_name(username: &str) -> bool {
+    !username.is_empty()
+        && username
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+}
+
+/// collect the ids and authors of these comments and their replies
+fn collect_comments(
+    comments: &[Comment],
+    comment_ids: &mut BTreeSet<i64>,
+    users: &mut BTreeSet<String>,
+) {
+    for comment in comments {
+        if comment.deleted || comment.dead {
+            continue;
+        }
+        comment_ids.insert(comment.id);
+        users.insert(comment.by.clone());
+        collect_comments(&comment.sub_comments, comment_ids, users);
+    }
+}
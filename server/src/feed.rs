@@ -0,0 +1,397 @@
+//! RSS and Atom feeds of the story listings and of the stories submitted by a user,
+//! ie: `/best.atom`, `/show.rss`, `/user/pg/submitted.atom`,
+//! and an Atom feed of the comments of a story, ie: `/item/8863/comments.atom`
+use crate::{
+    conditional,
+    ServerError,
+};
+use axum::{
+    extract::Path,
+    http::{HeaderMap, Uri},
+    response::{IntoResponse, Response},
+    routing::get,
+    Router,
+};
+use chrono::{DateTime, Utc};
+use common::{
+    api,
+    types::{
+        Comment, CommentFetchOptions, StoryItem, StoryOrder, StorySorting,
+        UserData,
+    },
+};
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum FeedFormat {
+    Rss,
+    Atom,
+}
+
+impl FeedFormat {
+    fn all() -> [Self; 2] {
+        [FeedFormat::Rss, FeedFormat::Atom]
+    }
+
+    fn extension(&self) -> &'static str {
+        match self {
+            FeedFormat::Rss => "rss",
+            FeedFormat::Atom => "atom",
+        }
+    }
+
+    fn content_type(&self) -> &'static str {
+        match self {
+            FeedFormat::Rss => "application/rss+xml; charset=utf-8",
+            FeedFormat::Atom => "application/atom+xml; charset=utf-8",
+        }
+    }
+}
+
+pub struct Feed {
+    pub title: String,
+    /// the url of the page this is the feed of
+    pub link: String,
+    /// the url of the feed itself
+    pub self_link: String,
+    pub entries: Vec<Entry>,
+}
+
+pub struct Entry {
+    /// the permalink of the entry, which is also its id
+    pub id: String,
+    pub title: String,
+    pub link: String,
+    /// the url of the discussion of the entry
+    pub comments: Option<String>,
+    pub author: String,
+    pub published: DateTime<Utc>,
+    /// the plain text summary
+    pub summary: String,
+    /// the html content
+    pub content: Option<String>,
+}
+
+/// add the routes to the feeds of each story listing and of the submissions of the users
+pub fn routes(mut router: Router) -> Router {
+    for sorting in StorySorting::all() {
+        for format in FeedFormat::all().iter().copied() {
+            router = router.route(
+                &format!("{}.{}", sorting.to_url(), format.extension()),
+                get(move |uri: Uri, headers: HeaderMap| async move {
+                    stories_feed(sorting, format, &uri, &headers).await
+                }),
+            );
+        }
+    }
+    for format in FeedFormat::all().iter().copied() {
+        router = router.route(
+            &format!("/user/:username/submitted.{}", format.extension()),
+            get(
+                move |Path(username): Path<String>,
+                      uri: Uri,
+                      headers: HeaderMap| async move {
+                    user_feed(&username, format, &uri, &headers).await
+                },
+            ),
+        );
+    }
+    router.route(
+        "/item/:story_id/comments.atom",
+        get(
+            move |Path(story_id): Path<i64>,
+                  uri: Uri,
+                  headers: HeaderMap| async move {
+                comments_feed(story_id, &uri, &headers).await
+            },
+        ),
+    )
+}
+
+lazy_static::lazy_static! {
+    /// the public url of the site from `HN_PUBLIC_URL`, ie: `https://hn.example.com`,
+    /// rather than the `Host` of the request which is chosen by the client
+    static ref PUBLIC_URL: String = std::env::var("HN_PUBLIC_URL")
+        .map(|url| url.trim_end_matches('/').to_string())
+        .unwrap_or_else(|_| "http://localhost:3030".to_string());
+}
+
+async fn stories_feed(
+    sorting: StorySorting,
+    format: FeedFormat,
+    uri: &Uri,
+    headers: &HeaderMap,
+) -> Response {
+    match api::get_stories_with_sorting(sorting, 1, StoryOrder::Rank).await {
+        Ok(story_list) => {
+            let site = site_url();
+            let feed = Feed {
+                title: format!("Hacker News: {}", sorting.to_str()),
+                link: format!("{}{}", site, sorting.to_url()),
+                self_link: format!(
+                    "{}{}.{}",
+                    site,
+                    sorting.to_url(),
+                    format.extension()
+                ),
+                entries: story_entries(site, &story_list.stories),
+            };
+            feed.respond(format, uri, headers)
+        }
+        Err(e) => ServerError::from(e).into_response(),
+    }
+}
+
+async fn user_feed(
+    username: &str,
+    format: FeedFormat,
+    uri: &Uri,
+    headers: &HeaderMap,
+) -> Response {
+    match api::get_user_page(username).await {
+        Ok(user_page) => {
+            let site = site_url();
+            let feed = Feed {
+                title: format!("Hacker News: {} submissions", user_page.id),
+                link: format!("{}{}", site, UserData::to_url(&user_page.id)),
+                self_link: format!(
+                    "{}{}/submitted.{}",
+                    site,
+                    UserData::to_url(&user_page.id),
+                    format.extension()
+                ),
+                entries: story_entries(site, &user_page.stories),
+            };
+            feed.respond(format, uri, headers)
+        }
+        Err(e) => ServerError::from(e).into_response(),
+    }
+}
+
+/// the newest comments in the comments feed of a story
+const COMMENTS_FEED_ENTRIES: usize = 50;
+
+/// The comments feed is of the newest comments anywhere in the thread,
+/// so as much of the thread as allowed is fetched, rather than the first few comments of the page.
+/// The largest comment tree is cached just like the one of the page.
+async fn comments_feed(
+    story_id: i64,
+    uri: &Uri,
+    headers: &HeaderMap,
+) -> Response {
+    let options = CommentFetchOptions::MAX;
+    match api::get_story_with_options(story_id, &options).await {
+        Ok(story_page) => {
+            let site = site_url();
+            let story_url = StoryItem::to_url(story_page.id);
+            let mut comments = vec![];
+            flatten(&story_page.comments, &mut comments);
+            comments.sort_by(|a, b| b.time.cmp(&a.time));
+            comments.truncate(COMMENTS_FEED_ENTRIES);
+            let feed = Feed {
+                title: format!("Hacker News: comments on {}", story_page.title),
+                link: format!("{}{}", site, story_url),
+                self_link: format!("{}{}/comments.atom", site, story_url),
+                entries: comments
+                    .into_iter()
+                    .map(|comment| {
+                        comment_entry(site, comment, &story_page.title)
+                    })
+                    .collect(),
+            };
+            feed.respond(FeedFormat::Atom, uri, headers)
+        }
+        Err(e) => ServerError::from(e).into_response(),
+    }
+}
+
+/// the comments and their replies in a flat list, leaving out the deleted and dead ones
+fn flatten<'a>(comments: &'a [Comment], flattened: &mut Vec<&'a Comment>) {
+    for comment in comments {
+        if !comment.deleted && !comment.dead {
+            flattened.push(comment);
+        }
+        flatten(&comment.sub_comments, flattened);
+    }
+}
+
+/// the entry of a comment, linking to its permalink
+fn comment_entry(site: &str, comment: &Comment, story_title: &str) -> Entry {
+    let permalink = format!("{}{}", site, Comment::to_url(comment.id));
+    Entry {
+        id: permalink.clone(),
+        title: format!("{} on: {}", comment.by, story_title),
+        link: permalink,
+        comments: None,
+        author: comment.by.clone(),
+        published: comment.time,
+        summary: String::new(),
+        content: Some(comment.text.clone()),
+    }
+}
+
+/// the entries of the stories, linking to the story url or to the discussion when there is none
+fn story_entries(site: &str, stories: &[StoryItem]) -> Vec<Entry> {
+    stories
+        .iter()
+        .filter(|story| !story.deleted && !story.dead)
+        .map(|story| {
+            let discussion = format!("{}{}", site, StoryItem::to_url(story.id));
+            Entry {
+                id: discussion.clone(),
+                title: story.title.clone(),
+                link: story.url.clone().unwrap_or_else(|| discussion.clone()),
+                summary: format!(
+                    "{} points by {} | {} comments",
+                    story.score, story.by, story.descendants
+                ),
+                comments: Some(discussion),
+                author: story.by.clone(),
+                published: story.time,
+                content: story.text.clone(),
+            }
+        })
+        .collect()
+}
+
+impl Feed {
+    /// the time of the latest entry
+    fn updated(&self) -> DateTime<Utc> {
+        self.entries
+            .iter()
+            .map(|entry| entry.published)
+            .max()
+            .unwrap_or_else(Utc::now)
+    }
+
+    pub fn respond(
+        &self,
+        format: FeedFormat,
+        uri: &Uri,
+        headers: &HeaderMap,
+    ) -> Response {
+        let xml = match format {
+            FeedFormat::Rss => self.to_rss(),
+            FeedFormat::Atom => self.to_atom(),
+        };
+        conditional::content(
+            uri,
+            headers,
+            format.content_type(),
+            xml.into_bytes(),
+        )
+    }
+
+    pub fn to_rss(&self) -> String {
+        let mut xml = String::from(
+            r#"<?xml version="1.0" encoding="utf-8"?>
+<rss version="2.0" xmlns:atom="http://www.w3.org/2005/Atom" xmlns:dc="http://purl.org/dc/elements/1.1/">
+<channel>
+"#,
+        );
+        xml += &format!(
+            "<title>{}</title>\n<link>{}</link>\n<description>{}</description>\n",
+            escape(&self.title),
+            escape(&self.link),
+            escape(&self.title),
+        );
+        xml += &format!(
+            "<atom:link href=\"{}\" rel=\"self\" type=\"application/rss+xml\"/>\n",
+            escape(&self.self_link)
+        );
+        xml += &format!(
+            "<lastBuildDate>{}</lastBuildDate>\n",
+            self.updated().to_rfc2822()
+        );
+        for entry in self.entries.iter() {
+            xml += "<item>\n";
+            xml += &format!("<title>{}</title>\n", escape(&entry.title));
+            xml += &format!("<link>{}</link>\n", escape(&entry.link));
+            xml += &format!(
+                "<guid isPermaLink=\"true\">{}</guid>\n",
+                escape(&entry.id)
+            );
+            if let Some(comments) = &entry.comments {
+                xml += &format!("<comments>{}</comments>\n", escape(comments));
+            }
+            xml += &format!("<dc:creator>{}</dc:creator>\n", escape(&entry.author));
+            xml += &format!(
+                "<pubDate>{}</pubDate>\n",
+                entry.published.to_rfc2822()
+            );
+            let description = entry.content.as_ref().unwrap_or(&entry.summary);
+            xml += &format!(
+                "<description>{}</description>\n",
+                escape(description)
+            );
+            xml += "</item>\n";
+        }
+        xml += "</channel>\n</rss>\n";
+        xml
+    }
+
+    pub fn to_atom(&self) -> String {
+        let mut xml = String::from(
+            r#"<?xml version="1.0" encoding="utf-8"?>
+<feed xmlns="http://www.w3.org/2005/Atom">
+"#,
+        );
+        xml += &format!("<title>{}</title>\n", escape(&self.title));
+        xml += &format!("<id>{}</id>\n", escape(&self.self_link));
+        xml += &format!("<link href=\"{}\"/>\n", escape(&self.link));
+        xml += &format!(
+            "<link href=\"{}\" rel=\"self\"/>\n",
+            escape(&self.self_link)
+        );
+        xml += &format!("<updated>{}</updated>\n", self.updated().to_rfc3339());
+        for entry in self.entries.iter() {
+            xml += "<entry>\n";
+            xml += &format!("<title>{}</title>\n", escape(&entry.title));
+            xml += &format!("<id>{}</id>\n", escape(&entry.id));
+            xml += &format!("<link href=\"{}\"/>\n", escape(&entry.link));
+            if let Some(comments) = &entry.comments {
+                xml += &format!(
+                    "<link href=\"{}\" rel=\"replies\" type=\"text/html\"/>\n",
+                    escape(comments)
+                );
+            }
+            xml += &format!(
+                "<author><name>{}</name></author>\n",
+                escape(&entry.author)
+            );
+            xml += &format!(
+                "<published>{}</published>\n",
+                entry.published.to_rfc3339()
+            );
+            xml += &format!(
+                "<updated>{}</updated>\n",
+                entry.published.to_rfc3339()
+            );
+            if !entry.summary.is_empty() {
+                xml += &format!("<summary>{}</summary>\n", escape(&entry.summary));
+            }
+            if let Some(content) = &entry.content {
+                xml += &format!(
+                    "<content type=\"html\">{}</content>\n",
+                    escape(content)
+                );
+            }
+            xml += "</entry>\n";
+        }
+        xml += "</feed>\n";
+        xml
+    }
+}
+
+/// the url this site is served at, used for the absolute links in the feeds
+pub fn site_url() -> &'static str {
+    &PUBLIC_URL
+}
+
+/// escape the text to be used in xml
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
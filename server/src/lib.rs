@@ -1,3 +1,432 @@
 #![deny(warnings)]
-pub use client::sauron;
+//! The pages of the app rendered on the server, the json api, the feeds and the assets,
+//! put together in the router of the `app`.
+#[cfg(feature = "openapi")]
+use common::openapi;
+use common::{
+    route::{Route, API_V1},
+    types::{
+        ApiError, ApiResponse, CommentFetchOptions, Pagination, StoryOrder,
+        StorySorting,
+    },
+};
+use client::App;
 pub use common::api;
+pub use client::sauron;
+use axum::{Json, extract::{Path, Query, rejection::{PathRejection, QueryRejection}},
+    handler::Handler,
+    http::{header, HeaderMap, HeaderValue, StatusCode, Uri}, response::Html,
+    routing::get, Router,
+    response::Response, response::IntoResponse};
+use serde::Deserialize;
+use thiserror::Error;
+use tower_http::{
+    compression::CompressionLayer, set_header::SetResponseHeaderLayer,
+};
+
+mod assets;
+mod conditional;
+pub mod export;
+mod feed;
+mod page;
+
+#[derive(Error, Debug)]
+pub enum ServerError{
+    #[error(transparent)]
+    Api(#[from] api::ServerError),
+    #[error("{0}")]
+    Http(#[from] axum::http::Error),
+    /// the path or the query of the request is malformed, ie: `?p=abc`
+    #[error("{0}")]
+    BadRequest(String),
+}
+
+impl ServerError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            ServerError::Api(api::ServerError::NotFound) => StatusCode::NOT_FOUND,
+            ServerError::Api(api::ServerError::Timeout) => {
+                StatusCode::GATEWAY_TIMEOUT
+            }
+            ServerError::Api(_) => StatusCode::BAD_GATEWAY,
+            ServerError::Http(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            ServerError::BadRequest(_) => StatusCode::BAD_REQUEST,
+        }
+    }
+
+    /// a stable code of the kind of the error, used in the json api
+    fn code(&self) -> &'static str {
+        match self {
+            ServerError::Api(e) => e.code(),
+            ServerError::Http(_) => "internal_error",
+            ServerError::BadRequest(_) => "bad_request",
+        }
+    }
+
+    /// a fixed message for the code of the error, which is shown to the client
+    /// since the error itself may have the url of the api or the path of the cassette
+    fn message(&self) -> &'static str {
+        match self.code() {
+            "not_found" => "the page could not be found",
+            "timeout" => "hacker news did not respond in time",
+            "bad_request" => "the request is malformed",
+            "internal_error" => "something went wrong on the server",
+            _ => "hacker news could not be reached",
+        }
+    }
+
+    /// log the error with its details, which are not shown to the client
+    fn log(&self) {
+        if self.status_code() != StatusCode::NOT_FOUND {
+            eprintln!("error {}: {}", self.code(), self);
+        }
+    }
+
+    /// the error in the json api response, used in the `/api/*` routes
+    fn into_json_response(self) -> Response {
+        self.log();
+        let error = ApiError {
+            code: self.code().to_string(),
+            message: self.message().to_string(),
+        };
+        (self.status_code(), Json(ApiResponse::<()>::error(error)))
+            .into_response()
+    }
+}
+
+/// The error is rendered as a page of the App, with the matching http status
+impl IntoResponse for ServerError{
+
+    fn into_response(self) -> Response {
+        self.log();
+        let app = App::with_error(self.message().to_string());
+        let index = page::index(&app).render_to_string();
+        (self.status_code(), Html(index)).into_response()
+    }
+}
+
+/// The query of the json story listing routes, ie: `/api/top?p=2&sort=score`
+#[derive(Deserialize, Debug)]
+struct StoriesQuery {
+    /// the page number, starting at 1
+    p: Option<usize>,
+    /// the order of the stories in the page, defaults to the ranking order
+    sort: Option<StoryOrder>,
+}
+
+/// The query of the json story item route, ie: `/api/item/123?depth=5&children=10&total=300`
+#[derive(Deserialize, Debug)]
+struct CommentQuery {
+    /// the number of levels of replies fetched below each top-level comment
+    depth: Option<usize>,
+    /// the number of replies fetched for each comment
+    children: Option<usize>,
+    /// the total number of comments fetched
+    total: Option<usize>,
+}
+
+impl CommentQuery {
+    fn options(&self) -> CommentFetchOptions {
+        let default = CommentFetchOptions::default();
+        CommentFetchOptions {
+            max_depth: self.depth.unwrap_or(default.max_depth),
+            max_children_per_level: self
+                .children
+                .unwrap_or(default.max_children_per_level),
+            max_total: self.total.unwrap_or(default.max_total),
+        }
+        .limit(&CommentFetchOptions::MAX)
+    }
+}
+
+/// the router of the whole app
+pub fn app() -> Router {
+    api_routes(feed::routes(assets::routes(Router::new())))
+        // the pages of the app are matched with the `Route` shared with the client
+        .fallback(render_route.into_service())
+        // gzip or brotli, whichever the client accepts
+        .layer(CompressionLayer::new())
+        // the caches keep the responses of each encoding apart
+        .layer(SetResponseHeaderLayer::appending(
+            header::VARY,
+            HeaderValue::from_static("accept-encoding"),
+        ))
+}
+
+/// The json api is mounted at `/api/v1`, and at `/api` for the consumers of the unversioned api
+fn api_routes(mut router: Router) -> Router {
+    for prefix in [API_V1, "/api"].iter() {
+        router = router
+            .route(&format!("{}/top", prefix), get(api_top_stories))
+            .route(&format!("{}/best", prefix), get(api_best_stories))
+            .route(&format!("{}/new", prefix), get(api_new_stories))
+            .route(&format!("{}/show", prefix), get(api_show_stories))
+            .route(&format!("{}/ask", prefix), get(api_ask_stories))
+            .route(&format!("{}/job", prefix), get(api_job_stories))
+            .route(&format!("{}/item/:story_id", prefix), get(api_story_item))
+            .route(
+                &format!("{}/comment/:comment_id", prefix),
+                get(api_comment_permalink),
+            )
+            .route(&format!("{}/user/:username", prefix), get(api_user_page));
+    }
+    #[cfg(feature = "openapi")]
+    let router = router.route(
+        &format!("{}/openapi.json", API_V1),
+        get(|| async { Json(openapi::openapi()) }),
+    );
+    router
+}
+
+async fn api_top_stories(
+    query: Result<Query<StoriesQuery>, QueryRejection>,
+    uri: Uri,
+    headers: HeaderMap,
+) -> Response {
+    json_stories(StorySorting::Top, query, &uri, &headers).await
+}
+async fn api_best_stories(
+    query: Result<Query<StoriesQuery>, QueryRejection>,
+    uri: Uri,
+    headers: HeaderMap,
+) -> Response {
+    json_stories(StorySorting::Best, query, &uri, &headers).await
+}
+async fn api_new_stories(
+    query: Result<Query<StoriesQuery>, QueryRejection>,
+    uri: Uri,
+    headers: HeaderMap,
+) -> Response {
+    json_stories(StorySorting::New, query, &uri, &headers).await
+}
+async fn api_show_stories(
+    query: Result<Query<StoriesQuery>, QueryRejection>,
+    uri: Uri,
+    headers: HeaderMap,
+) -> Response {
+    json_stories(StorySorting::Show, query, &uri, &headers).await
+}
+
+async fn api_ask_stories(
+    query: Result<Query<StoriesQuery>, QueryRejection>,
+    uri: Uri,
+    headers: HeaderMap,
+) -> Response {
+    json_stories(StorySorting::Ask, query, &uri, &headers).await
+}
+
+async fn api_job_stories(
+    query: Result<Query<StoriesQuery>, QueryRejection>,
+    uri: Uri,
+    headers: HeaderMap,
+) -> Response {
+    json_stories(StorySorting::Job, query, &uri, &headers).await
+}
+
+async fn api_story_item(
+    story_id: Result<Path<i64>, PathRejection>,
+    query: Result<Query<CommentQuery>, QueryRejection>,
+    uri: Uri,
+    headers: HeaderMap,
+) -> Response {
+    match (story_id, query) {
+        (Ok(Path(story_id)), Ok(Query(query))) => {
+            json_story_page(story_id, &query.options(), &uri, &headers).await
+        }
+        (Err(e), _) => bad_request(e),
+        (_, Err(e)) => bad_request(e),
+    }
+}
+
+async fn api_comment_permalink(
+    comment_id: Result<Path<i64>, PathRejection>,
+    uri: Uri,
+    headers: HeaderMap,
+) -> Response {
+    match comment_id {
+        Ok(Path(comment_id)) => {
+            json_comment_permalink(comment_id, &uri, &headers).await
+        }
+        Err(e) => bad_request(e),
+    }
+}
+
+ async fn  api_user_page(
+    username: Result<Path<String>, PathRejection>,
+    uri: Uri,
+    headers: HeaderMap,
+) -> Response {
+    match username {
+        Ok(Path(username)) => json_user_page(&username, &uri, &headers).await,
+        Err(e) => bad_request(e),
+    }
+}
+
+/// The malformed path or query of the json api routes are rejected with a json error,
+/// rather than the plain text rejection of the extractor
+fn bad_request(rejection: impl std::fmt::Display) -> Response {
+    ServerError::BadRequest(rejection.to_string()).into_json_response()
+}
+
+/// render the page of the app matching the path and query of the url
+async fn render_route(uri: Uri, headers: HeaderMap) -> Response {
+    let url = uri.path_and_query().map(|pq| pq.as_str()).unwrap_or("/");
+    match Route::parse(url) {
+        Some(Route::Stories { sorting, page }) => {
+            render_stories(sorting, page, &uri, &headers).await
+        }
+        Some(Route::Item { id, options }) => {
+            render_story_page(
+                id,
+                &options.limit(&CommentFetchOptions::MAX),
+                &uri,
+                &headers,
+            )
+            .await
+        }
+        Some(Route::Comment(comment_id)) => {
+            render_comment_permalink(comment_id, &uri, &headers).await
+        }
+        Some(Route::User(username)) => {
+            render_user_page(&username, &uri, &headers).await
+        }
+        None => not_found(uri).await,
+    }
+}
+
+/// Unknown paths are rendered as a not found page of the App,
+/// or a json error in the `/api/*` routes
+async fn not_found(uri: Uri) -> Response {
+    if uri.path().starts_with("/api/") {
+        ServerError::from(api::ServerError::NotFound).into_json_response()
+    } else {
+        let app = App::with_not_found(uri.path());
+        let index = page::index(&app).render_to_string();
+        (StatusCode::NOT_FOUND, Html(index)).into_response()
+    }
+}
+
+async fn render_stories(
+    sorting: StorySorting,
+    page_number: usize,
+    uri: &Uri,
+    headers: &HeaderMap,
+) -> Response {
+     match api::get_stories_with_sorting(sorting, page_number, StoryOrder::Rank).await{
+        Ok(stories) => {
+            let app = App::with_stories(stories);
+            let index = page::index(&app).render_to_string();
+            conditional::html(uri, headers, index)
+        }
+        Err(e) => ServerError::from(e).into_response()
+    }
+}
+
+async fn render_story_page(
+    item: i64,
+    options: &CommentFetchOptions,
+    uri: &Uri,
+    headers: &HeaderMap,
+) -> Response {
+     match api::get_story_with_options(item, options).await{
+        Ok(story_page) => {
+            let app = App::with_story(story_page);
+            let index = page::index(&app).render_to_string();
+            conditional::html(uri, headers, index)
+        }
+        Err(e) => ServerError::from(e).into_response()
+    }
+}
+
+async fn render_comment_permalink(
+    comment_id: i64,
+    uri: &Uri,
+    headers: &HeaderMap,
+) -> Response {
+    match api::get_comment(comment_id).await{
+        Ok(comment) => {
+            let app = App::with_comment_permalink(comment);
+            let index = page::index(&app).render_to_string();
+            conditional::html(uri, headers, index)
+        }
+        Err(e) => ServerError::from(e).into_response()
+    }
+}
+
+async fn render_user_page(
+    username: &str,
+    uri: &Uri,
+    headers: &HeaderMap,
+) -> Response {
+    match api::get_user_page(username).await{
+        Ok(user_page) => {
+            let app = App::with_user_page(user_page);
+            let index = page::index(&app).render_to_string();
+            conditional::html(uri, headers, index)
+        }
+        Err(e) => ServerError::from(e).into_response()
+    }
+}
+
+async fn json_story_page(
+    story_id: i64,
+    options: &CommentFetchOptions,
+    uri: &Uri,
+    headers: &HeaderMap,
+) -> Response {
+    match api::get_story_with_options(story_id, options).await{
+        Ok(story_page) => {
+            conditional::json(uri, headers, &ApiResponse::ok(story_page))
+        }
+        Err(e) => ServerError::from(e).into_json_response()
+    }
+}
+
+async fn json_user_page(
+    username: &str,
+    uri: &Uri,
+    headers: &HeaderMap,
+) -> Response {
+    match api::get_user_page(username).await{
+        Ok(user_page) => {
+            conditional::json(uri, headers, &ApiResponse::ok(user_page))
+        }
+        Err(e) => ServerError::from(e).into_json_response()
+    }
+}
+
+async fn json_comment_permalink(
+    comment_id: i64,
+    uri: &Uri,
+    headers: &HeaderMap,
+) -> Response {
+    match api::get_comment(comment_id).await{
+        Ok(comment) => {
+            conditional::json(uri, headers, &ApiResponse::ok(comment))
+        }
+        Err(e) => ServerError::from(e).into_json_response(),
+    }
+}
+
+async fn json_stories(
+    sorting: StorySorting,
+    query: Result<Query<StoriesQuery>, QueryRejection>,
+    uri: &Uri,
+    headers: &HeaderMap,
+) -> Response {
+     let query = match query {
+         Ok(Query(query)) => query,
+         Err(e) => return bad_request(e),
+     };
+     let page = query.p.unwrap_or(1);
+     let order = query.sort.unwrap_or_default();
+     match api::get_stories_with_sorting(sorting, page, order).await{
+        Ok(story_list) => {
+            let pagination = Pagination::from(&story_list);
+            let response =
+                ApiResponse::ok(story_list.stories).with_pagination(pagination);
+            conditional::json(uri, headers, &response)
+        }
+        Err(e) => ServerError::from(e).into_json_response()
+     }
+}
@@ -1,68 +1,45 @@
 #![deny(warnings)]
-use common::types::StorySorting;
-use client::App;
+use common::{
+    api,
+    snapshot::Snapshot,
+    types::CommentFetchOptions,
+};
+use server::export;
 use std::net::SocketAddr;
-use common::api;
-pub use client::sauron;
-use axum::{Json, extract::Path,
-    http::StatusCode, response::Html,
-    http::{header::{HeaderMap, HeaderName, HeaderValue}},
-    routing::get, Router,
-    response::Response, response::IntoResponse};
-use thiserror::Error;
+use std::time::Duration;
 
-mod page;
+mod cli;
 
 #[cfg(not(feature = "use-port-80"))]
 const DEFAULT_PORT: u16 = 3030;
 
-#[derive(Error, Debug)]
-pub enum ServerError{
-    #[error(transparent)]
-    Api(#[from] api::ServerError),
-    #[error("{0}")]
-    Http(#[from] axum::http::Error),
-}
-
-impl IntoResponse for ServerError{
+#[tokio::main]
+async fn main() {
+    match api_client_from_env() {
+        Ok(client) => api::set_default_client(client),
+        Err(e) => {
+            eprintln!("unable to configure the hacker news api: {}", e);
+            std::process::exit(1);
+        }
+    }
 
-    fn into_response(self) -> Response {
-        (
-           StatusCode::INTERNAL_SERVER_ERROR,
-           format!("Server error. Error: {}", self),
-        )
-        .into_response()
+    match cli::Command::from_args() {
+        Ok(cli::Command::Serve) => serve().await,
+        Ok(cli::Command::Snapshot { out, pages }) => {
+            write_snapshot(&out, pages).await
+        }
+        Ok(cli::Command::Export { out, pages }) => {
+            export::export(&out, pages).await.expect("must write the export")
+        }
+        Err(e) => {
+            eprintln!("{}\n{}", e, cli::USAGE);
+            std::process::exit(2);
+        }
     }
 }
 
-#[tokio::main]
-async fn main() {
-
-    let route = Router::new()
-        .route("/", get(top_stories))
-        .route("/favicon.ico", get(favicon_ico))
-        .route("/favicon.svg", get(favicon_svg))
-        .route("/style.css", get(style_css))
-        .route("/pkg/client.js", get(client_js))
-        .route("/pkg/client_bg.wasm", get(client_bg_wasm))
-        .route("/top", get(top_stories))
-        .route("/best", get(best_stories))
-        .route("/new", get(new_stories))
-        .route("/show", get(show_stories))
-        .route("/ask", get(ask_stories))
-        .route("/job", get(job_stories))
-        .route("/item/:story_id", get(story_item))
-        .route("/user/:username", get(user))
-        .route("/comment/:comment_id", get(comment))
-        .route("/api/top", get(api_top_stories))
-        .route("/api/best", get(api_best_stories))
-        .route("/api/new", get(api_new_stories))
-        .route("/api/show", get(api_show_stories))
-        .route("/api/ask", get(api_ask_stories))
-        .route("/api/job", get(api_job_stories))
-        .route("/api/item/:story_id", get(api_story_item))
-        .route("/api/comment/:comment_id", get(api_comment_permalink))
-        .route("/api/user/:username", get(api_user_page));
+async fn serve() {
+    let route = server::app();
 
     #[cfg(not(feature = "use-port-80"))]
     let port = if let Ok(port) = std::env::var("PORT") {
@@ -92,196 +69,93 @@ async fn main() {
 
 }
 
-async fn favicon_ico() -> (HeaderMap, Vec<u8>){
-    let mut headers = HeaderMap::new();
-    headers.insert(HeaderName::from_static("content-type"),
-        HeaderValue::from_static("image/x-icon"));
-
-    (headers, include_bytes!("../../client/favicon.ico").to_vec())
-}
-
-async fn favicon_svg() -> Vec<u8>{
-   include_bytes!("../../client/favicon.svg").to_vec()
-}
-
-async fn style_css() -> (HeaderMap, String) {
-    let mut headers = HeaderMap::new();
-    headers.insert(HeaderName::from_static("content-type"),
-        HeaderValue::from_static("text/css"));
-
-    (headers, include_str!("../../client/style.css").to_string())
-}
-
-async fn client_js() -> (HeaderMap, String) {
-    let mut headers = HeaderMap::new();
-    headers.insert(HeaderName::from_static("content-type"),
-        HeaderValue::from_static("text/javascript"));
-
-    (headers, include_str!("../../client/pkg/client.js").to_string())
-}
-
-async fn client_bg_wasm() -> (HeaderMap, Vec<u8>) {
-    let mut headers = HeaderMap::new();
-    headers.insert(HeaderName::from_static("content-type"),
-        HeaderValue::from_static("application/wasm"));
-
-    (headers, include_bytes!("../../client/pkg/client_bg.wasm").to_vec())
-}
-
-async fn top_stories() -> Response  {
-    render_stories(StorySorting::Top).await
-}
-async fn best_stories() -> Response {
-    render_stories(StorySorting::Best).await
-}
-async fn new_stories() -> Response {
-    render_stories(StorySorting::New).await
-}
-async fn show_stories() -> Response  {
-    render_stories(StorySorting::Show).await
-}
-
-async fn ask_stories() -> Response  {
-    render_stories(StorySorting::Ask).await
-}
-
-async fn job_stories() -> Response  {
-    render_stories(StorySorting::Job).await
-}
-
-
-async fn api_top_stories() -> Response  {
-    json_stories(StorySorting::Top).await
-}
-async fn api_best_stories() -> Response {
-    json_stories(StorySorting::Best).await
-}
-async fn api_new_stories() -> Response {
-    json_stories(StorySorting::New).await
-}
-async fn api_show_stories() -> Response  {
-    json_stories(StorySorting::Show).await
-}
-
-async fn api_ask_stories() -> Response  {
-    json_stories(StorySorting::Ask).await
-}
-
-async fn api_job_stories() -> Response  {
-    json_stories(StorySorting::Job).await
-}
-
-async fn story_item(Path(item): Path<i64>) -> Response{
-    render_story_page(item).await
-}
-
-async fn user(Path(username): Path<String>) -> Response {
-    render_user_page(&username).await
-}
-
-async fn comment(Path(comment_id): Path<i64>) -> Response {
-    render_comment_permalink(comment_id).await
-}
-
-async fn api_story_item(Path(story_id): Path<i64>) -> Response {
-    json_story_page(story_id).await
-}
-
-async fn api_comment_permalink(Path(comment_id): Path<i64>) -> Response {
-    json_comment_permalink(comment_id).await
-}
-
- async fn  api_user_page(Path(username): Path<String>) -> Response {
-    json_user_page(&username).await
-}
-
-async fn render_stories(
-    sorting: StorySorting,
-) -> Response {
-     match api::get_stories_with_sorting(sorting).await{
-        Ok(stories) => {
-            let app = App::with_stories(stories);
-            let index = page::index(&app).render_to_string();
-            Html(index).into_response()
-        }
-        Err(e) => ServerError::from(e).into_response()
+/// write a snapshot of the first `pages` of every listing, to be served on static hosting
+async fn write_snapshot(out: &std::path::Path, pages: usize) {
+    let snapshot = Snapshot::create(
+        &api::default_client(),
+        pages,
+        &CommentFetchOptions::default(),
+    )
+    .await
+    .expect("must fetch the story listings");
+    let json = serde_json::to_string(&snapshot).expect("must serialize");
+    std::fs::write(out, json).expect("must write the snapshot");
+    println!(
+        "snapshot of {} items and {} users written to: {}",
+        snapshot.items.len(),
+        snapshot.users.len(),
+        out.display()
+    );
+}
+
+/// Configure the client to the hacker news api from the environment variables:
+///  - `HN_API_BASE_URL` the base url of the api or a mirror of it
+///  - `HN_API_TIMEOUT` the timeout of each attempt of a request to the api, in seconds
+///  - `HN_API_RETRIES` the retries of a request to the api which failed for a transient reason
+///  - `HN_API_USER_AGENT` the user agent sent to the api
+///  - `HN_API_MAX_IN_FLIGHT` the number of requests to the api which can be in flight at once
+///  - `HN_CACHE_DIR` the directory of the on-disk cache of the api responses
+///  - `HN_SNAPSHOT` a snapshot file which is read instead of the api
+///  - `HN_RECORD_DIR` a cassette directory the responses of the api are recorded into
+///  - `HN_REPLAY_DIR` a cassette directory the responses are replayed from instead of the api,
+///    which can not be set along with `HN_RECORD_DIR`
+fn api_client_from_env() -> Result<api::HnClient, String> {
+    let mut builder = api::HnClient::builder();
+    if let Ok(base_url) = std::env::var("HN_API_BASE_URL") {
+        builder = builder.base_url(base_url);
     }
-}
-
-async fn render_story_page(item: i64) -> Response {
-     match api::get_story(item).await{
-        Ok(story_page) => {
-            let app = App::with_story(story_page);
-            let index = page::index(&app).render_to_string();
-            Html(index).into_response()
+    if let Ok(timeout) = std::env::var("HN_API_TIMEOUT") {
+        if let Ok(timeout) = timeout.parse::<u64>() {
+            builder = builder.timeout(Duration::from_secs(timeout));
         }
-        Err(e) => ServerError::from(e).into_response()
     }
-}
-
-async fn render_comment_permalink(
-    comment_id: i64,
-) -> Response {
-    match api::get_comment(comment_id).await{
-        Ok(comment) => {
-            let app = App::with_comment_permalink(comment);
-            let index = page::index(&app).render_to_string();
-            Html(index).into_response()
+    if let Ok(retries) = std::env::var("HN_API_RETRIES") {
+        if let Ok(max_retries) = retries.parse::<u32>() {
+            builder = builder.retry(api::RetryPolicy {
+                max_retries,
+                ..Default::default()
+            });
         }
-        Err(e) => ServerError::from(e).into_response()
     }
-}
-
-async fn render_user_page(
-    username: &str,
-) -> Response {
-    match api::get_user_page(username).await{
-        Ok(user_page) => {
-            let app = App::with_user_page(user_page);
-            let index = page::index(&app).render_to_string();
-            Html(index).into_response()
-        }
-        Err(e) => ServerError::from(e).into_response()
+    if let Ok(user_agent) = std::env::var("HN_API_USER_AGENT") {
+        builder = builder.user_agent(user_agent);
     }
-}
-
-async fn json_story_page(
-    story_id: i64,
-) -> Response {
-    match api::get_story(story_id).await{
-        Ok(story_page) => {
-            let json = serde_json::to_string(&story_page).expect("must serialize");
-            Json(json).into_response()
+    if let Ok(max_in_flight) = std::env::var("HN_API_MAX_IN_FLIGHT") {
+        if let Ok(max_in_flight) = max_in_flight.parse::<usize>() {
+            builder = builder.max_in_flight(max_in_flight);
         }
-        Err(e) => ServerError::from(e).into_response()
     }
-}
-
-async fn json_user_page(
-    username: &str,
-) -> Response {
-    match api::get_user_page(username).await{
-        Ok(user_page) => {
-            Json(user_page).into_response()
+    if let Ok(cache_dir) = std::env::var("HN_CACHE_DIR") {
+        match api::DiskCacheStore::open(&cache_dir) {
+            Ok(cache_store) => builder = builder.cache_store(cache_store),
+            Err(e) => eprintln!(
+                "unable to open the cache at {}, caching in memory instead: {}",
+                cache_dir, e
+            ),
         }
-        Err(e) => ServerError::from(e).into_response()
     }
-}
-
-async fn json_comment_permalink(
-    comment_id: i64,
-) -> Response {
-    match api::get_comment(comment_id).await{
-        Ok(comment) => Json(comment).into_response(),
-        Err(e) => ServerError::from(e).into_response(),
+    if let Ok(snapshot_file) = std::env::var("HN_SNAPSHOT") {
+        let json = std::fs::read_to_string(&snapshot_file).map_err(|e| {
+            format!("unable to read the snapshot {}: {}", snapshot_file, e)
+        })?;
+        let snapshot = Snapshot::from_json(&json).map_err(|e| {
+            format!("{} is not a valid snapshot: {}", snapshot_file, e)
+        })?;
+        builder = builder.snapshot(snapshot);
     }
-}
-
-async fn json_stories(
-    sorting: StorySorting,
-) -> Response {
-     match api::get_stories_with_sorting(sorting).await{
-        Ok(stories) => Json(stories).into_response(),
-        Err(e) => ServerError::from(e).into_response()
-     }
+    match (std::env::var("HN_RECORD_DIR"), std::env::var("HN_REPLAY_DIR")) {
+        (Ok(_), Ok(_)) => {
+            return Err("HN_RECORD_DIR and HN_REPLAY_DIR are both set, \
+                a cassette is either recorded or replayed"
+                .to_string());
+        }
+        (Ok(record_dir), Err(_)) => {
+            builder = builder.cassette(api::Cassette::record(record_dir));
+        }
+        (Err(_), Ok(replay_dir)) => {
+            builder = builder.cassette(api::Cassette::replay(replay_dir));
+        }
+        (Err(_), Err(_)) => (),
+    }
+    builder.build().map_err(|e| e.to_string())
 }
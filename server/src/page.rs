@@ -1,14 +1,29 @@
+use crate::assets;
 use crate::sauron;
 use crate::sauron::prelude::*;
 use client::{App, Msg};
+use common::api;
+
+lazy_static::lazy_static! {
+    /// The api the app fetches from once it runs in the browser, from `HN_BROWSER_API_BASE_URL`.
+    /// This is a setting of its own, rather than the api of the server,
+    /// which may be a mirror or a stand-in the browser can not reach.
+    static ref BROWSER_API_BASE_URL: String = std::env::var("HN_BROWSER_API_BASE_URL")
+        .unwrap_or_else(|_| api::BASE_URL.to_string());
+}
 
 /// We are creating an index page.
 /// From the `App` supplied, we can derive the view by calling `App.view` function.
 /// we extract the state and serialize it.
 pub fn index(app: &App) -> Node<Msg> {
-    println!("app: {:#?}", app);
     let serialized_state = serde_json::to_string(&app).unwrap();
     let serialized_state = serialized_state.replace('`', r#"${"`"}"#);
+    let api_base_url = serde_json::to_string(&*BROWSER_API_BASE_URL)
+        .expect("must serialize");
+    // the assets are linked with their fingerprinted urls, which are cached by the browsers
+    let style_css = assets::url("style.css");
+    let favicon_ico = assets::url("favicon.ico");
+    let favicon_svg = assets::url("favicon.svg");
     node! {
         <!doctype html>
         <html lang="en">
@@ -16,20 +31,25 @@ pub fn index(app: &App) -> Node<Msg> {
                <meta http-equiv="Content-type" content="text/html; charset=utf-8"/>
                <meta name="referrer" content="origin"/>
                <meta name="viewport" content="width=device-width, initial-scale=1.0"/>
-               <link rel="stylesheet" type="text/css" href="/style.css"/>
-               <link rel="shortcut icon" href="/favicon.ico"/>
-               <link rel="shortcut icon" href="/favicon.svg" type="image/x-icon"/>
+               <link rel="stylesheet" type="text/css" href=style_css />
+               <link rel="shortcut icon" href=favicon_ico />
+               <link rel="shortcut icon" href=favicon_svg type="image/x-icon"/>
                <title>"Hacker News"</title>
                 <script type="module">
                     {text!("
-                          import init, {{ main }} from '/pkg/client.js';
+                          import init, {{ main, set_api_base_url }} from '{}';
                           async function start() {{
-                            await init();
+                            await init('{}');
+                            set_api_base_url({});
                             let app_state = String.raw`{}`;
                             await main(app_state);
                           }}
                           start();
-                    ",serialized_state)}
+                    ",
+                    assets::url("pkg/client.js"),
+                    assets::url("pkg/client_bg.wasm"),
+                    api_base_url,
+                    serialized_state)}
                 </script>
             </head>
             { app.view() }
@@ -0,0 +1,213 @@
+#![deny(warnings)]
+//! The routes of the server against the recorded fixtures served by `mock-hn`, with no network
+use axum::{
+    body::Body,
+    http::{header, HeaderMap, HeaderValue, Request, StatusCode},
+};
+use common::{
+    api::{self, HnClient},
+    types::{ApiResponse, StoryItem, StoryPageData},
+};
+use mock_hn::MockHn;
+use tokio::runtime::Runtime;
+use tower::ServiceExt;
+
+lazy_static::lazy_static! {
+    static ref MOCK_HN: MockHn = MockHn::start();
+    /// the runtime shared by the tests,
+    /// since the pooled connections of the default client are tied to the runtime they are made in
+    static ref RUNTIME: Runtime = {
+        api::set_default_client(
+            HnClient::new(MOCK_HN.base_url()).expect("must build the client"),
+        );
+        Runtime::new().expect("must build the runtime")
+    };
+}
+
+struct Response {
+    status: StatusCode,
+    headers: HeaderMap,
+    body: String,
+}
+
+fn get(uri: &str) -> Response {
+    get_with(uri, HeaderMap::new())
+}
+
+fn get_with(uri: &str, headers: HeaderMap) -> Response {
+    RUNTIME.block_on(async {
+        let mut request = Request::builder().uri(uri);
+        for (name, value) in headers.iter() {
+            request = request.header(name, value);
+        }
+        let response = server::app()
+            .oneshot(request.body(Body::empty()).expect("must be a request"))
+            .await
+            .expect("must respond");
+        let status = response.status();
+        let headers = response.headers().clone();
+        let body = hyper::body::to_bytes(response.into_body())
+            .await
+            .expect("must read the body");
+        Response {
+            status,
+            headers,
+            body: String::from_utf8(body.to_vec()).expect("must be utf8"),
+        }
+    })
+}
+
+#[test]
+fn stories_page_is_rendered() {
+    let response = get("/top");
+    assert_eq!(response.status, StatusCode::OK);
+    assert_eq!(
+        response.headers[header::CONTENT_TYPE],
+        "text/html; charset=utf-8"
+    );
+    assert!(response
+        .body
+        .contains("My YC app: Dropbox - Throw away your USB drive"));
+    assert!(response.body.contains("Ask HN: The Arc Effect"));
+}
+
+#[test]
+fn story_page_is_rendered_with_the_comments() {
+    let response = get("/item/8863");
+    assert_eq!(response.status, StatusCode::OK);
+    assert!(response.body.contains("I have a few qualms with this app"));
+    assert!(response.body.contains("BrandonM"));
+}
+
+#[test]
+fn user_page_is_rendered() {
+    let response = get("/user/dhouston");
+    assert_eq!(response.status, StatusCode::OK);
+    assert!(response.body.contains("Founder/CEO of Dropbox"));
+}
+
+#[test]
+fn unknown_pages_are_not_found() {
+    assert_eq!(get("/nothing/here").status, StatusCode::NOT_FOUND);
+    assert_eq!(get("/item/1").status, StatusCode::NOT_FOUND);
+}
+
+#[test]
+fn unchanged_item_is_not_modified() {
+    let response = get("/api/v1/item/121003");
+    let etag = response.headers[header::ETAG].clone();
+    let mut headers = HeaderMap::new();
+    headers.insert(header::IF_NONE_MATCH, etag);
+    let response = get_with("/api/v1/item/121003", headers);
+    assert_eq!(response.status, StatusCode::NOT_MODIFIED);
+    assert!(response.body.is_empty());
+}
+
+#[test]
+fn responses_vary_by_their_encoding() {
+    let response = get("/api/v1/item/121003");
+    assert_eq!(response.headers[header::VARY], "accept-encoding");
+}
+
+#[test]
+fn unchanged_page_is_not_modified_since_it_was_first_served() {
+    let response = get("/top");
+    let last_modified = response.headers[header::LAST_MODIFIED].clone();
+    let mut headers = HeaderMap::new();
+    headers.insert(header::IF_MODIFIED_SINCE, last_modified);
+    let response = get_with("/top", headers);
+    assert_eq!(response.status, StatusCode::NOT_MODIFIED);
+
+    let mut headers = HeaderMap::new();
+    headers.insert(
+        header::IF_MODIFIED_SINCE,
+        HeaderValue::from_static("Thu, 01 Jan 1970 00:00:00 GMT"),
+    );
+    let response = get_with("/top", headers);
+    assert_eq!(response.status, StatusCode::OK);
+}
+
+#[test]
+fn pages_are_not_revalidated_by_a_future_date() {
+    let mut headers = HeaderMap::new();
+    headers.insert(
+        header::IF_MODIFIED_SINCE,
+        HeaderValue::from_static("Fri, 01 Jan 2100 00:00:00 GMT"),
+    );
+    let response = get_with("/api/v1/top", headers);
+    assert_eq!(response.status, StatusCode::OK);
+}
+
+#[test]
+fn api_stories_are_paginated() {
+    let response = get("/api/v1/top");
+    assert_eq!(response.status, StatusCode::OK);
+    let stories: ApiResponse<Vec<StoryItem>> =
+        serde_json::from_str(&response.body).expect("must be the stories");
+    let ids: Vec<i64> = stories.data.unwrap().iter().map(|s| s.id).collect();
+    assert_eq!(ids, vec![8863, 121003, 192327]);
+    let pagination = stories.pagination.expect("must have a pagination");
+    assert_eq!(pagination.total, 3);
+    assert_eq!(pagination.next_page, None);
+}
+
+#[test]
+fn api_item_has_the_comments() {
+    for uri in ["/api/v1/item/8863", "/api/item/8863"].iter() {
+        let response = get(uri);
+        assert_eq!(response.status, StatusCode::OK);
+        let story: ApiResponse<StoryPageData> =
+            serde_json::from_str(&response.body).expect("must be the story");
+        let story = story.data.expect("must have the story");
+        assert_eq!(story.comments.len(), 2);
+    }
+}
+
+#[test]
+fn api_errors_are_in_the_envelope() {
+    for uri in ["/api/v1/user/nobody", "/api/v1/nothing"].iter() {
+        let response = get(uri);
+        assert_eq!(response.status, StatusCode::NOT_FOUND);
+        let error: ApiResponse<()> =
+            serde_json::from_str(&response.body).expect("must be the error");
+        assert_eq!(error.error.expect("must have the error").code, "not_found");
+    }
+}
+
+#[test]
+fn malformed_api_requests_are_in_the_envelope() {
+    let uris = [
+        "/api/v1/top?p=abc",
+        "/api/v1/top?sort=x",
+        "/api/v1/item/abc",
+        "/api/v1/item/8863?depth=-1",
+        "/api/v1/comment/abc",
+    ];
+    for uri in uris.iter() {
+        let response = get(uri);
+        assert_eq!(response.status, StatusCode::BAD_REQUEST, "{}", uri);
+        let error: ApiResponse<()> =
+            serde_json::from_str(&response.body).expect("must be the error");
+        let error = error.error.expect("must have the error");
+        assert_eq!(error.code, "bad_request");
+        assert_eq!(error.message, "the request is malformed");
+    }
+}
+
+#[test]
+fn comments_feed_is_newest_first() {
+    let response = get("/item/8863/comments.atom");
+    assert_eq!(response.status, StatusCode::OK);
+    let newest = response.body.find("/comment/9272").expect("must have 9272");
+    let oldest = response.body.find("/comment/8917").expect("must have 8917");
+    assert!(newest < oldest);
+}
+
+#[test]
+fn stories_feed_has_the_stories() {
+    let response = get("/job.rss");
+    assert_eq!(response.status, StatusCode::OK);
+    assert!(response
+        .body
+        .contains("<title>Justin.tv is looking for a Lead Flash Engineer!</title>"));
+}